@@ -0,0 +1,232 @@
+use reqwest::Client;
+use serde::Serialize;
+
+use crate::error::check_body;
+use crate::limit::AsyncLimiter;
+use crate::retry::retry_after;
+use crate::validate::check_dataset;
+use crate::{
+    parse, AttributeInfo, BiomartError, DatasetConfiguration, DatasetInfo, FilterInfo, Formatter,
+    MartClientBuilder, MartInfo, Query, QueryBuilder, Response, RetryPolicy, REQUEST_ID,
+};
+
+/// Non-blocking counterpart of `MartClient`, for use within async runtimes.
+///
+/// # Example
+///
+/// ```no_run
+/// use rust_biomart::AsyncMartClient;
+/// # #[tokio::main]
+/// # async fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mart_client = AsyncMartClient::new("http://ensembl.org:80/biomart/martservice");
+/// let marts = mart_client.marts().await?;
+/// for mart in &marts {
+///     println!("{}", mart.name());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub struct AsyncMartClient {
+    server: String,
+    client: Client,
+    pub(crate) retry_policy: RetryPolicy,
+    pub(crate) limiter: AsyncLimiter,
+}
+
+impl AsyncMartClient {
+    /// Creates a client for `server` with default settings (60s timeout, gzip).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the underlying HTTP client cannot be initialized.
+    /// Use `MartClientBuilder::build_async` to handle this case or to change the defaults.
+    pub fn new<S: Into<String>>(server: S) -> Self {
+        MartClientBuilder::new(server)
+            .build_async()
+            .expect("Failed building HTTP client")
+    }
+
+    /// Creates a client for `server` using a pre-configured `reqwest::Client`.
+    pub fn from_client<S: Into<String>>(server: S, client: Client) -> Self {
+        AsyncMartClient {
+            server: server.into(),
+            client,
+            retry_policy: RetryPolicy::none(),
+            limiter: AsyncLimiter::default(),
+        }
+    }
+
+    async fn make_request<T: Serialize + ?Sized>(&self, query: &T) -> Result<String, BiomartError> {
+        let _permit = self.limiter.acquire().await;
+        let q = self
+            .client
+            .post(&self.server)
+            .header(reqwest::header::ACCEPT_ENCODING, "gzip")
+            .query(&[("requestid", REQUEST_ID)])
+            .query(query);
+        let response = q.send().await?;
+        if response.status().is_success() {
+            Ok(response.text().await?)
+        } else {
+            Err(BiomartError::Status {
+                status: response.status(),
+                retry_after: retry_after(response.headers()),
+            })
+        }
+    }
+
+    async fn request_and_parse<P, R, T>(&self, query: &T, parser: P) -> Result<R, BiomartError>
+    where
+        P: Fn(String) -> Result<R, BiomartError>,
+        T: Serialize + ?Sized,
+    {
+        let mut attempt = 1;
+        loop {
+            match self.make_request(query).await.and_then(&parser) {
+                Err(e) => match self.retry_policy.next_delay(attempt, &e) {
+                    Some(delay) => {
+                        tokio::time::delay_for(delay).await;
+                        attempt += 1;
+                    }
+                    None => return Err(e),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    /// See `MartClient::query`.
+    pub async fn query(&self, query: &Query) -> Result<Response, BiomartError> {
+        if query.formatter() == Some(Formatter::Xls) {
+            return Err(BiomartError::InvalidQuery(
+                "XLS results are binary and not supported by AsyncMartClient, \
+                 use the blocking MartClient::query_to_writer to save them"
+                    .into(),
+            ));
+        }
+        let s = query.to_string();
+        self.request_and_parse(&[("query", &s)], |raw| parse::query_result(raw, query))
+            .await
+    }
+
+    /// See `MartClient::validate`.
+    pub async fn validate(&self, mart: &str, query: &Query) -> Result<(), BiomartError> {
+        self.validate_builder(mart, &QueryBuilder::from_query(query)?)
+            .await
+    }
+
+    /// See `MartClient::validate_builder`.
+    pub async fn validate_builder(
+        &self,
+        mart: &str,
+        query: &QueryBuilder,
+    ) -> Result<(), BiomartError> {
+        let mut issues = vec![];
+        for dataset in &query.datasets {
+            let filters = self.filters(mart, &dataset.name).await?;
+            let attributes = self.attributes(mart, &dataset.name).await?;
+            issues.extend(check_dataset(dataset, &filters, &attributes));
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(BiomartError::Validation(issues))
+        }
+    }
+
+    /// See `MartClient::count`.
+    pub async fn count(&self, query: &Query) -> Result<u64, BiomartError> {
+        let s = query.as_count().to_string();
+        self.request_and_parse(&[("query", &s)], |raw| {
+            check_body(raw, false).and_then(parse::count)
+        })
+        .await
+    }
+
+    /// See `MartClient::marts`.
+    pub async fn marts(&self) -> Result<Vec<MartInfo>, BiomartError> {
+        self.request_and_parse(&[("type", "registry")], |raw| {
+            check_body(raw, false).and_then(parse::registry)
+        })
+        .await
+    }
+
+    /// See `MartClient::datasets`.
+    pub async fn datasets(&self, mart: &str) -> Result<Vec<DatasetInfo>, BiomartError> {
+        self.request_and_parse(&[("mart", mart), ("type", "datasets")], |raw| {
+            check_body(raw, false).and_then(parse::datasets)
+        })
+        .await
+    }
+
+    /// See `MartClient::filters`.
+    pub async fn filters(
+        &self,
+        mart: &str,
+        dataset: &str,
+    ) -> Result<Vec<FilterInfo>, BiomartError> {
+        self.request_and_parse(
+            &[("mart", mart), ("dataset", dataset), ("type", "filters")],
+            |raw| check_body(raw, false).and_then(parse::filters),
+        )
+        .await
+    }
+
+    /// See `MartClient::attributes`.
+    pub async fn attributes(
+        &self,
+        mart: &str,
+        dataset: &str,
+    ) -> Result<Vec<AttributeInfo>, BiomartError> {
+        self.request_and_parse(
+            &[("mart", mart), ("dataset", dataset), ("type", "attributes")],
+            |raw| check_body(raw, false).and_then(parse::attributes),
+        )
+        .await
+    }
+
+    /// See `MartClient::configuration`.
+    pub async fn configuration(
+        &self,
+        mart: &str,
+        dataset: &str,
+    ) -> Result<DatasetConfiguration, BiomartError> {
+        self.request_and_parse(
+            &[
+                ("mart", mart),
+                ("dataset", dataset),
+                ("type", "configuration"),
+            ],
+            |raw| check_body(raw, false).and_then(parse::configuration),
+        )
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{AsyncMartClient, QueryBuilder};
+
+    #[tokio::test]
+    async fn async_query() {
+        let mart_client = AsyncMartClient::new("http://ensembl.org:80/biomart/martservice");
+        let query = QueryBuilder::new()
+            .mart("ensembl")
+            .dataset("hsapiens_gene_ensembl")
+            .attributes(vec!["affy_hg_u133_plus_2", "entrezgene_id"])
+            .filter(
+                "affy_hg_u133_plus_2",
+                vec!["202763_at", "209310_s_at", "207500_at"],
+            )
+            .build();
+        let response = mart_client.query(&query).await.unwrap();
+        assert_eq!(
+            "AFFY HG U133 Plus 2 probe	NCBI gene ID
+209310_s_at	837
+207500_at	838
+202763_at	836
+",
+            response.raw()
+        );
+    }
+}
@@ -0,0 +1,379 @@
+use std::collections::HashSet;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use crate::{BiomartError, FilterOperation, Formatter, Query, QueryBuilder, Response, RetryPolicy};
+
+/// Reported to the `Chunking::on_progress` callback whenever a batch has finished.
+#[derive(Debug)]
+pub struct ChunkProgress {
+    /// Index of the finished batch.
+    pub chunk: usize,
+    /// Number of batches finished so far.
+    pub completed: usize,
+    pub total: usize,
+}
+
+type ProgressCallback = Arc<dyn Fn(&ChunkProgress) + Send + Sync>;
+
+/// Describes how `MartClient::query_chunked` splits the values of a filter into batches.
+///
+/// # Example
+///
+/// ```
+/// use rust_biomart::{Chunking, MartClient, QueryBuilder};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+/// let genes = vec!["ENSG00000141510", "ENSG00000012048", "ENSG00000139618"];
+/// let mut query = QueryBuilder::new();
+/// query
+///     .dataset("hsapiens_gene_ensembl")
+///     .attributes(vec!["ensembl_gene_id", "external_gene_name"])
+///     .filter("ensembl_gene_id", genes);
+/// let mut chunking = Chunking::new("ensembl_gene_id", 2);
+/// chunking
+///     .deduplicate(true)
+///     .workers(2)
+///     .on_progress(|p| eprintln!("{}/{} batches done", p.completed, p.total));
+/// let response = mart_client.query_chunked(&query, &chunking)?;
+/// assert_eq!(3, response.records().len());
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct Chunking {
+    filter: String,
+    chunk_size: usize,
+    deduplicate: bool,
+    workers: usize,
+    pub(crate) retry_policy: Option<RetryPolicy>,
+    on_progress: Option<ProgressCallback>,
+}
+
+impl Chunking {
+    /// Splits the values of `filter` into batches of at most `chunk_size` values.
+    pub fn new<S: Into<String>>(filter: S, chunk_size: usize) -> Self {
+        Chunking {
+            filter: filter.into(),
+            chunk_size: chunk_size.max(1),
+            deduplicate: false,
+            workers: 1,
+            retry_policy: None,
+            on_progress: None,
+        }
+    }
+
+    /// Whether rows occurring in the results of several batches are only kept once.
+    pub fn deduplicate(&mut self, deduplicate: bool) -> &mut Self {
+        self.deduplicate = deduplicate;
+        self
+    }
+
+    /// Number of batches that run at the same time. Defaults to 1, i.e. sequential execution.
+    /// Note that the rate limit and `max_in_flight` setting of the client still apply.
+    pub fn workers(&mut self, workers: usize) -> &mut Self {
+        self.workers = workers.max(1);
+        self
+    }
+
+    /// Retry policy for each batch, in place of the one of the client.
+    /// A failing batch is retried on its own, without affecting the others.
+    pub fn retry_policy(&mut self, policy: &RetryPolicy) -> &mut Self {
+        self.retry_policy = Some(policy.clone());
+        self
+    }
+
+    /// Registers a callback invoked whenever a batch has finished.
+    pub fn on_progress<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(&ChunkProgress) + Send + Sync + 'static,
+    {
+        self.on_progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Builds one query per batch of filter values.
+    /// Only TSV, GTF and FASTA results can be merged, so other formats are rejected.
+    pub(crate) fn split(&self, query: &QueryBuilder) -> Result<Vec<Query>, BiomartError> {
+        match query.formatter {
+            Formatter::Tsv | Formatter::Gtf | Formatter::Fasta => {}
+            formatter => {
+                return Err(BiomartError::InvalidQuery(format!(
+                    "{} results cannot be merged, use TSV for chunked queries",
+                    formatter.name()
+                )))
+            }
+        }
+        // the first filter with values named `filter`, by dataset and position
+        let (d, f, values) = query
+            .datasets
+            .iter()
+            .enumerate()
+            .flat_map(|(d, dataset)| {
+                dataset
+                    .filters
+                    .iter()
+                    .enumerate()
+                    .map(move |(f, filter)| (d, f, filter))
+            })
+            .find_map(|(d, f, (name, operation))| match operation {
+                FilterOperation::Match(values) | FilterOperation::IdList(values)
+                    if *name == self.filter =>
+                {
+                    Some((d, f, values))
+                }
+                _ => None,
+            })
+            .ok_or_else(|| {
+                BiomartError::InvalidQuery(format!(
+                    "No filter with values named {:?} to split",
+                    self.filter
+                ))
+            })?;
+        if values.is_empty() {
+            return Ok(vec![query.build()]);
+        }
+        Ok(values
+            .chunks(self.chunk_size)
+            .map(|chunk| {
+                let mut sub_query = query.clone();
+                let operation = &mut sub_query.datasets[d].filters[f].1;
+                *operation = match operation {
+                    FilterOperation::IdList(_) => FilterOperation::IdList(chunk.to_vec()),
+                    _ => FilterOperation::Match(chunk.to_vec()),
+                };
+                sub_query.build()
+            })
+            .collect())
+    }
+
+    /// Runs `queries` on up to `workers` threads and returns the results in the order of `queries`.
+    /// Once a batch has failed, no further batches are started and the error is returned.
+    pub(crate) fn run<F>(
+        &self,
+        queries: Vec<Query>,
+        query: F,
+    ) -> Result<Vec<Response>, BiomartError>
+    where
+        F: Fn(&Query) -> Result<Response, BiomartError> + Sync,
+    {
+        let total = queries.len();
+        let next = AtomicUsize::new(0);
+        let completed = AtomicUsize::new(0);
+        let failed = AtomicBool::new(false);
+        let results = Mutex::new((0..total).map(|_| None).collect::<Vec<_>>());
+        thread::scope(|scope| {
+            for _ in 0..self.workers.min(total) {
+                scope.spawn(|| {
+                    while !failed.load(Ordering::SeqCst) {
+                        let chunk = next.fetch_add(1, Ordering::SeqCst);
+                        if chunk >= total {
+                            break;
+                        }
+                        let result = query(&queries[chunk]);
+                        if result.is_err() {
+                            failed.store(true, Ordering::SeqCst);
+                        }
+                        results.lock().unwrap()[chunk] = Some(result);
+                        let completed = completed.fetch_add(1, Ordering::SeqCst) + 1;
+                        if let Some(callback) = &self.on_progress {
+                            callback(&ChunkProgress {
+                                chunk,
+                                completed,
+                                total,
+                            });
+                        }
+                    }
+                });
+            }
+        });
+        results
+            .into_inner()
+            .unwrap()
+            .into_iter()
+            .flatten()
+            .collect()
+    }
+
+    /// Concatenates the results of the batches of `query`, keeping only the first header line.
+    /// FASTA results are merged (and deduplicated) by sequence rather than by line.
+    pub(crate) fn merge<I>(&self, responses: I, query: Query) -> Response
+    where
+        I: IntoIterator<Item = Response>,
+    {
+        let header = query.has_header_line();
+        let fasta = query.formatter() == Some(Formatter::Fasta);
+        let mut raw = String::new();
+        let mut seen = HashSet::new();
+        for (i, response) in responses.into_iter().enumerate() {
+            let mut lines = response.raw.lines();
+            if header {
+                match lines.next() {
+                    Some(line) if i == 0 => {
+                        raw.push_str(line);
+                        raw.push('\n');
+                    }
+                    _ => {}
+                }
+            }
+            let lines = lines.filter(|line| !line.is_empty());
+            let entries = if fasta {
+                fasta_entries(lines)
+            } else {
+                lines.map(|line| format!("{}\n", line)).collect()
+            };
+            for entry in entries {
+                if !self.deduplicate || seen.insert(entry.clone()) {
+                    raw.push_str(&entry);
+                }
+            }
+        }
+        Response { raw, query }
+    }
+}
+
+/// Groups the lines of a FASTA result into records, each starting with its header line.
+fn fasta_entries<'a, I: Iterator<Item = &'a str>>(lines: I) -> Vec<String> {
+    let mut entries: Vec<String> = vec![];
+    for line in lines {
+        match entries.last_mut() {
+            Some(entry) if !line.starts_with('>') => entry.push_str(line),
+            _ => entries.push(line.to_string()),
+        }
+        entries.last_mut().unwrap().push('\n');
+    }
+    entries
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::{BiomartError, Chunking, Formatter, QueryBuilder, Response};
+
+    #[test]
+    fn split_filter_values() {
+        let mut query = QueryBuilder::new();
+        query
+            .dataset("hsapiens_gene_ensembl")
+            .attribute("ensembl_gene_id")
+            .filter("ensembl_gene_id", vec!["g1", "g2", "g3", "g4", "g5"])
+            .filter_bool("with_hgnc", true);
+        let queries = Chunking::new("ensembl_gene_id", 2).split(&query).unwrap();
+        assert_eq!(3, queries.len());
+        assert!(queries[0].to_string().contains("value=\"g1,g2\""));
+        assert!(queries[2].to_string().contains("value=\"g5\""));
+        assert!(queries[2].to_string().contains("with_hgnc"));
+        assert!(Chunking::new("hgnc_symbol", 2).split(&query).is_err());
+        query.formatter(Formatter::Html);
+        assert!(Chunking::new("ensembl_gene_id", 2).split(&query).is_err());
+    }
+
+    #[test]
+    fn split_linked_query() {
+        let mut query = QueryBuilder::new();
+        query
+            .dataset("hsapiens_gene_ensembl")
+            .attribute("ensembl_gene_id")
+            .filter("ensembl_gene_id", vec!["g1", "g2", "g3"])
+            .linked_dataset("mmusculus_gene_ensembl")
+            .attribute("ensembl_gene_id")
+            .filter("ensembl_gene_id", vec!["m1"])
+            .filter_bool("ensembl_gene_id", true);
+        let queries = Chunking::new("ensembl_gene_id", 2).split(&query).unwrap();
+        assert_eq!(2, queries.len());
+        let linked = queries[1].to_string();
+        let linked = &linked[linked.find("mmusculus_gene_ensembl").unwrap()..];
+        assert!(linked.contains("value=\"m1\""), "{}", linked);
+        assert!(linked.contains("excluded=\"0\""), "{}", linked);
+        assert!(!linked.contains("g3"), "{}", linked);
+        assert!(queries[1].to_string().contains("value=\"g3\""));
+    }
+
+    #[test]
+    fn merge_fasta() {
+        let query = QueryBuilder::new().formatter(Formatter::Fasta).build();
+        let responses = vec![
+            Response {
+                raw: ">g1|t1\nMEEP\nQSDP\n>g1|t2\nMEEP\nQSDP\n".into(),
+                query: query.clone(),
+            },
+            Response {
+                raw: ">g1|t1\nMEEP\nQSDP\n>g2|t3\nMEEP\n".into(),
+                query: query.clone(),
+            },
+        ];
+        let merged = Chunking::new("ensembl_transcript_id", 2)
+            .deduplicate(true)
+            .merge(responses, query);
+        assert_eq!(
+            ">g1|t1\nMEEP\nQSDP\n>g1|t2\nMEEP\nQSDP\n>g2|t3\nMEEP\n",
+            merged.raw()
+        );
+    }
+
+    #[test]
+    fn merge_responses() {
+        let responses = vec![
+            Response {
+                raw: "Gene stable ID\tGene name\ng1\tTP53\ng2\tBRCA2\n".into(),
+                query: QueryBuilder::new().build(),
+            },
+            Response {
+                raw: "Gene stable ID\tGene name\ng2\tBRCA2\ng3\tNRAS\n".into(),
+                query: QueryBuilder::new().build(),
+            },
+        ];
+        let merged = Chunking::new("ensembl_gene_id", 2)
+            .deduplicate(true)
+            .merge(responses, QueryBuilder::new().build());
+        assert_eq!(
+            "Gene stable ID\tGene name\ng1\tTP53\ng2\tBRCA2\ng3\tNRAS\n",
+            merged.raw()
+        );
+    }
+
+    #[test]
+    fn run_in_parallel() {
+        let mut query = QueryBuilder::new();
+        query
+            .dataset("hsapiens_gene_ensembl")
+            .attribute("ensembl_gene_id")
+            .filter("ensembl_gene_id", (0..20).map(|i| format!("g{}", i)));
+        let progress = Arc::new(AtomicUsize::new(0));
+        let counter = progress.clone();
+        let mut chunking = Chunking::new("ensembl_gene_id", 3);
+        chunking.workers(4).on_progress(move |p| {
+            assert_eq!(7, p.total);
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let queries = chunking.split(&query).unwrap();
+        let responses = chunking
+            .run(queries, |q| {
+                // finish later chunks first to check that the order is kept
+                let xml = q.to_string();
+                let first = xml.find("value=\"g").unwrap() + 8;
+                let i: u64 = xml[first..].split(',').next().unwrap().parse().unwrap();
+                thread::sleep(Duration::from_millis(40 - 2 * i));
+                Ok(Response {
+                    raw: format!("Gene stable ID\ng{}\n", i),
+                    query: QueryBuilder::new().build(),
+                })
+            })
+            .unwrap();
+        assert_eq!(7, progress.load(Ordering::SeqCst));
+        let merged = chunking.merge(responses, query.build());
+        assert_eq!(
+            "Gene stable ID\ng0\ng3\ng6\ng9\ng12\ng15\ng18\n",
+            merged.raw()
+        );
+
+        let queries = chunking.split(&query).unwrap();
+        let result = chunking.run(queries, |_| Err(BiomartError::Incomplete));
+        assert!(result.is_err());
+    }
+}
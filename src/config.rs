@@ -0,0 +1,402 @@
+use getset::Getters;
+use xmltree::{Element, XMLNode};
+
+use crate::BiomartError;
+
+/// Configuration of a dataset as returned by `MartClient::configuration`, describing
+/// how its attributes and filters are organized in pages, groups and collections.
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct DatasetConfiguration {
+    dataset: String,
+    display_name: String,
+    attribute_pages: Vec<AttributePage>,
+    filter_pages: Vec<FilterPage>,
+}
+
+impl DatasetConfiguration {
+    /// The attributes selected by default, e.g. in the BioMart web interface.
+    pub fn default_attributes(&self) -> Vec<&AttributeDescription> {
+        self.attributes()
+            .filter(|attribute| attribute.default)
+            .collect()
+    }
+
+    /// All attributes, in the order of their pages, groups and collections.
+    pub fn attributes(&self) -> impl Iterator<Item = &AttributeDescription> {
+        self.attribute_pages
+            .iter()
+            .flat_map(|page| &page.groups)
+            .flat_map(|group| &group.collections)
+            .flat_map(|collection| &collection.attributes)
+    }
+
+    /// All filters, in the order of their pages, groups and collections.
+    /// Filters of filter containers are listed as options of the container.
+    pub fn filters(&self) -> impl Iterator<Item = &FilterDescription> {
+        self.filter_pages
+            .iter()
+            .flat_map(|page| &page.groups)
+            .flat_map(|group| &group.collections)
+            .flat_map(|collection| &collection.filters)
+    }
+
+    /// The pages listing the attribute `name`.
+    pub fn attribute_pages_of(&self, name: &str) -> Vec<&AttributePage> {
+        self.attribute_pages
+            .iter()
+            .filter(|page| {
+                page.groups
+                    .iter()
+                    .flat_map(|group| &group.collections)
+                    .flat_map(|collection| &collection.attributes)
+                    .any(|attribute| attribute.name == name)
+            })
+            .collect()
+    }
+
+    pub(crate) fn from_element(element: &Element) -> Result<Self, BiomartError> {
+        if element.name != "DatasetConfig" {
+            return Err(BiomartError::UnexpectedBody {
+                expected: "a dataset configuration",
+                snippet: format!("<{}>", element.name),
+            });
+        }
+        Ok(DatasetConfiguration {
+            dataset: attribute(element, "dataset"),
+            display_name: attribute(element, "displayName"),
+            attribute_pages: children(element, "AttributePage")
+                .map(AttributePage::from_element)
+                .collect(),
+            filter_pages: children(element, "FilterPage")
+                .map(FilterPage::from_element)
+                .collect(),
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct AttributePage {
+    name: String,
+    display_name: String,
+    /// Formatters supporting the attributes of this page, e.g. `"tsv"` or `"fasta"`.
+    formats: Vec<String>,
+    hidden: bool,
+    groups: Vec<AttributeGroup>,
+}
+
+impl AttributePage {
+    fn from_element(element: &Element) -> Self {
+        AttributePage {
+            name: attribute(element, "internalName"),
+            display_name: attribute(element, "displayName"),
+            formats: attribute(element, "outFormats")
+                .split(',')
+                .map(str::trim)
+                .filter(|format| !format.is_empty())
+                .map(str::to_string)
+                .collect(),
+            hidden: hidden(element),
+            groups: children(element, "AttributeGroup")
+                .map(|group| AttributeGroup {
+                    name: attribute(group, "internalName"),
+                    display_name: attribute(group, "displayName"),
+                    hidden: hidden(group),
+                    collections: children(group, "AttributeCollection")
+                        .map(|collection| AttributeCollection {
+                            name: attribute(collection, "internalName"),
+                            display_name: attribute(collection, "displayName"),
+                            hidden: hidden(collection),
+                            attributes: children(collection, "AttributeDescription")
+                                .map(AttributeDescription::from_element)
+                                .collect(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct AttributeGroup {
+    name: String,
+    display_name: String,
+    hidden: bool,
+    collections: Vec<AttributeCollection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct AttributeCollection {
+    name: String,
+    display_name: String,
+    hidden: bool,
+    attributes: Vec<AttributeDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct AttributeDescription {
+    name: String,
+    display_name: String,
+    description: String,
+    /// Whether the attribute is selected by default.
+    default: bool,
+    hidden: bool,
+}
+
+impl AttributeDescription {
+    fn from_element(element: &Element) -> Self {
+        AttributeDescription {
+            name: attribute(element, "internalName"),
+            display_name: attribute(element, "displayName"),
+            description: attribute(element, "description"),
+            default: attribute(element, "default") == "true",
+            hidden: hidden(element),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct FilterPage {
+    name: String,
+    display_name: String,
+    hidden: bool,
+    groups: Vec<FilterGroup>,
+}
+
+impl FilterPage {
+    fn from_element(element: &Element) -> Self {
+        FilterPage {
+            name: attribute(element, "internalName"),
+            display_name: attribute(element, "displayName"),
+            hidden: hidden(element),
+            groups: children(element, "FilterGroup")
+                .map(|group| FilterGroup {
+                    name: attribute(group, "internalName"),
+                    display_name: attribute(group, "displayName"),
+                    hidden: hidden(group),
+                    collections: children(group, "FilterCollection")
+                        .map(|collection| FilterCollection {
+                            name: attribute(collection, "internalName"),
+                            display_name: attribute(collection, "displayName"),
+                            hidden: hidden(collection),
+                            filters: children(collection, "FilterDescription")
+                                .map(FilterDescription::from_element)
+                                .collect(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct FilterGroup {
+    name: String,
+    display_name: String,
+    hidden: bool,
+    collections: Vec<FilterCollection>,
+}
+
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct FilterCollection {
+    name: String,
+    display_name: String,
+    hidden: bool,
+    filters: Vec<FilterDescription>,
+}
+
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct FilterDescription {
+    name: String,
+    display_name: String,
+    description: String,
+    /// Type of the filter, e.g. `"list"`, `"text"`, `"boolean"` or `"id_list"`.
+    kind: String,
+    /// Supported operations, e.g. `"="` and `"in"`.
+    qualifiers: Vec<String>,
+    multiple_values: bool,
+    hidden: bool,
+    options: Vec<FilterOption>,
+}
+
+impl FilterDescription {
+    fn from_element(element: &Element) -> Self {
+        let qualifiers = match attribute(element, "legalQualifiers") {
+            qualifiers if qualifiers.is_empty() => attribute(element, "qualifier"),
+            qualifiers => qualifiers,
+        };
+        FilterDescription {
+            name: attribute(element, "internalName"),
+            display_name: attribute(element, "displayName"),
+            description: attribute(element, "description"),
+            kind: attribute(element, "type"),
+            qualifiers: qualifiers
+                .split(',')
+                .map(str::trim)
+                .filter(|qualifier| !qualifier.is_empty())
+                .map(str::to_string)
+                .collect(),
+            multiple_values: attribute(element, "multipleValues") == "1",
+            hidden: hidden(element),
+            options: children(element, "Option")
+                .map(FilterOption::from_element)
+                .collect(),
+        }
+    }
+
+    /// Whether this is a filter container, whose options are filters themselves
+    /// (e.g. the list of ID filters offered in a single menu).
+    pub fn is_container(&self) -> bool {
+        !self.options.is_empty() && self.options.iter().all(|option| option.kind.is_some())
+    }
+}
+
+/// A value of a list filter, or a filter of a filter container.
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct FilterOption {
+    name: String,
+    display_name: String,
+    value: String,
+    /// Type of the filter, for options of a filter container.
+    kind: Option<String>,
+    /// Options of a filter container entry, e.g. the values of a list filter.
+    options: Vec<FilterOption>,
+    /// Options of other filters that depend on choosing this option.
+    push_actions: Vec<PushAction>,
+}
+
+impl FilterOption {
+    fn from_element(element: &Element) -> Self {
+        FilterOption {
+            name: attribute(element, "internalName"),
+            display_name: attribute(element, "displayName"),
+            value: attribute(element, "value"),
+            kind: element.attributes.get("type").cloned(),
+            options: children(element, "Option")
+                .map(FilterOption::from_element)
+                .collect(),
+            push_actions: children(element, "PushAction")
+                .map(|action| PushAction {
+                    name: attribute(action, "internalName"),
+                    filter: attribute(action, "ref"),
+                    options: children(action, "Option")
+                        .map(FilterOption::from_element)
+                        .collect(),
+                })
+                .collect(),
+        }
+    }
+}
+
+/// Restricts the options of `filter` once an option is chosen, e.g. the bands
+/// available for a chosen chromosome.
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct PushAction {
+    name: String,
+    /// Name of the filter whose options are set.
+    filter: String,
+    options: Vec<FilterOption>,
+}
+
+fn attribute(element: &Element, name: &str) -> String {
+    element.attributes.get(name).cloned().unwrap_or_default()
+}
+
+fn hidden(element: &Element) -> bool {
+    attribute(element, "hidden") == "true" || attribute(element, "hideDisplay") == "true"
+}
+
+fn children<'a>(element: &'a Element, name: &'a str) -> impl Iterator<Item = &'a Element> {
+    element
+        .children
+        .iter()
+        .filter_map(XMLNode::as_element)
+        .filter(move |child| child.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::parse;
+
+    #[test]
+    fn parse_configuration() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE DatasetConfig>
+<DatasetConfig dataset="hsapiens_gene_ensembl" displayName="Human genes" type="TableSet">
+  <FilterPage internalName="filters" displayName="FILTERS">
+    <FilterGroup internalName="REGION" displayName="REGION:">
+      <FilterCollection internalName="chromosome" displayName="Chromosome/scaffold">
+        <FilterDescription internalName="chromosome_name" displayName="Chromosome/scaffold name" type="list" legalQualifiers="=" multipleValues="1">
+          <Option internalName="17" displayName="17" value="17" isSelectable="true">
+            <PushAction internalName="band_push" ref="band_start">
+              <Option internalName="p13.1" displayName="p13.1" value="p13.1"/>
+            </PushAction>
+          </Option>
+        </FilterDescription>
+      </FilterCollection>
+    </FilterGroup>
+    <FilterGroup internalName="GENE" displayName="GENE:">
+      <FilterCollection internalName="id_list_limit" displayName="Input external references ID list">
+        <FilterDescription internalName="id_list_limit_filters" displayName="ID list" type="list">
+          <Option internalName="ensembl_gene_id" displayName="Gene stable ID(s)" type="id_list" legalQualifiers="=,in"/>
+          <Option internalName="hgnc_symbol" displayName="HGNC symbol(s)" type="id_list" legalQualifiers="=,in"/>
+        </FilterDescription>
+      </FilterCollection>
+    </FilterGroup>
+  </FilterPage>
+  <AttributePage internalName="feature_page" displayName="Features" outFormats="html,txt,csv,tsv,xls">
+    <AttributeGroup internalName="gene" displayName="GENE:">
+      <AttributeCollection internalName="ensembl_attributes" displayName="Ensembl">
+        <AttributeDescription internalName="ensembl_gene_id" displayName="Gene stable ID" default="true"/>
+        <AttributeDescription internalName="description" displayName="Gene description" hidden="false"/>
+      </AttributeCollection>
+    </AttributeGroup>
+  </AttributePage>
+  <AttributePage internalName="homologs" displayName="Homologues" outFormats="html,txt,csv,tsv,xls">
+    <AttributeGroup internalName="gene" displayName="GENE:">
+      <AttributeCollection internalName="ensembl_attributes" displayName="Ensembl">
+        <AttributeDescription internalName="ensembl_gene_id" displayName="Gene stable ID"/>
+      </AttributeCollection>
+    </AttributeGroup>
+  </AttributePage>
+</DatasetConfig>"#;
+        let config = parse::configuration(xml.into()).unwrap();
+        assert_eq!("hsapiens_gene_ensembl", config.dataset());
+        assert_eq!(2, config.attribute_pages().len());
+        assert_eq!(
+            vec!["html", "txt", "csv", "tsv", "xls"],
+            *config.attribute_pages()[0].formats()
+        );
+        assert_eq!(3, config.attributes().count());
+        let defaults = config.default_attributes();
+        assert_eq!(1, defaults.len());
+        assert_eq!("ensembl_gene_id", defaults[0].name());
+        assert_eq!(2, config.attribute_pages_of("ensembl_gene_id").len());
+
+        let filters = config.filters().collect::<Vec<_>>();
+        assert_eq!(2, filters.len());
+        assert_eq!("list", filters[0].kind());
+        assert!(*filters[0].multiple_values());
+        assert!(!filters[0].is_container());
+        let push_action = &filters[0].options()[0].push_actions()[0];
+        assert_eq!("band_start", push_action.filter());
+        assert_eq!("p13.1", push_action.options()[0].value());
+        assert!(filters[1].is_container());
+        assert_eq!(Some("id_list".to_string()), *filters[1].options()[0].kind());
+
+        assert!(parse::configuration("<Query/>".into()).is_err());
+        assert!(parse::configuration("Problem retrieving datasetconfig".into()).is_err());
+    }
+}
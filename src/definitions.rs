@@ -1,7 +1,5 @@
 use serde::de::Unexpected;
 use serde::{de, Deserialize, Deserializer, Serialize};
-use std::error::Error;
-use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -133,25 +131,3 @@ where
         )),
     }
 }
-
-#[derive(Debug)]
-pub(crate) struct ServerError;
-
-#[derive(Debug)]
-pub(crate) struct StatusError(pub(crate) reqwest::StatusCode);
-
-impl Error for ServerError {}
-
-impl Error for StatusError {}
-
-impl Display for ServerError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        f.write_str("Server error")
-    }
-}
-
-impl Display for StatusError {
-    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
-        f.write_fmt(format_args!("Error, status code: {}", self.0))
-    }
-}
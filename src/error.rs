@@ -0,0 +1,277 @@
+use std::error::Error;
+use std::fmt::{Display, Formatter};
+use std::time::Duration;
+
+use getset::Getters;
+
+use crate::ValidationIssue;
+
+/// Errors returned by `MartClient` and the query machinery.
+#[derive(Debug)]
+pub enum BiomartError {
+    /// The request could not be sent or its body could not be read.
+    Transport(reqwest::Error),
+    /// Reading or writing a streamed result failed.
+    Io(std::io::Error),
+    /// The server answered with a non-success HTTP status code.
+    Status {
+        status: reqwest::StatusCode,
+        /// The delay requested by the server via the `Retry-After` header, if any.
+        retry_after: Option<Duration>,
+    },
+    /// The server reported an error in the body of an otherwise successful response.
+    Server(ServerError),
+    /// The server answered with an HTML page (e.g. during maintenance) instead of data.
+    UnexpectedHtml { title: Option<String> },
+    /// The result lacks the completion stamp, i.e. it was truncated.
+    Incomplete,
+    /// The response body could not be parsed as XML.
+    Xml {
+        source: serde_xml_rs::Error,
+        /// The beginning of the offending body.
+        snippet: String,
+    },
+    /// The response body could not be parsed as TSV.
+    Csv(csv::Error),
+    /// A row of the result could not be parsed.
+    Record {
+        line: Option<u64>,
+        column: Option<String>,
+        message: String,
+    },
+    /// The response body does not have the expected form, e.g. a count that is not a number.
+    UnexpectedBody {
+        /// What the body should have contained.
+        expected: &'static str,
+        /// The beginning of the offending body.
+        snippet: String,
+    },
+    /// The query is not valid and was not sent.
+    InvalidQuery(String),
+    /// The query does not match the metadata of its datasets, see `MartClient::validate`.
+    Validation(Vec<ValidationIssue>),
+}
+
+impl BiomartError {
+    /// Whether the same request may succeed when sent again,
+    /// i.e. timeouts, connection failures, 429/5xx status codes and truncated results.
+    pub fn is_retryable(&self) -> bool {
+        match self {
+            BiomartError::Transport(e) => e.is_timeout() || e.is_connect() || e.is_body(),
+            BiomartError::Status { status, .. } => {
+                status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            BiomartError::UnexpectedHtml { .. } | BiomartError::Incomplete => true,
+            _ => false,
+        }
+    }
+}
+
+/// An error message embedded by BioMart in the response body.
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct ServerError {
+    exception: Option<String>,
+    message: String,
+}
+
+impl ServerError {
+    pub fn new<S: Into<String>>(exception: Option<String>, message: S) -> Self {
+        ServerError {
+            exception,
+            message: message.into(),
+        }
+    }
+
+    /// Parses messages like
+    /// `Query ERROR: caught BioMart::Exception::Usage: Filter foo NOT FOUND`.
+    fn parse(message: &str) -> Self {
+        let message = message.trim();
+        let message = message.strip_prefix("caught ").unwrap_or(message);
+        if message.starts_with(EXCEPTION_PREFIX) {
+            if let Some(idx) = message.find(": ") {
+                return ServerError::new(Some(message[..idx].into()), message[idx + 2..].trim());
+            }
+        }
+        ServerError::new(None, message)
+    }
+}
+
+impl Error for ServerError {}
+
+impl Display for ServerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match &self.exception {
+            Some(exception) => f.write_fmt(format_args!("{}: {}", exception, self.message)),
+            None => f.write_str(&self.message),
+        }
+    }
+}
+
+impl Error for BiomartError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            BiomartError::Transport(e) => Some(e),
+            BiomartError::Io(e) => Some(e),
+            BiomartError::Server(e) => Some(e),
+            BiomartError::Xml { source, .. } => Some(source),
+            BiomartError::Csv(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl Display for BiomartError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            BiomartError::Transport(e) => f.write_fmt(format_args!("Request failed: {}", e)),
+            BiomartError::Io(e) => f.write_fmt(format_args!("I/O error: {}", e)),
+            BiomartError::Status { status, .. } => {
+                f.write_fmt(format_args!("Error, status code: {}", status))
+            }
+            BiomartError::Server(e) => f.write_fmt(format_args!("Server error: {}", e)),
+            BiomartError::UnexpectedHtml { title } => match title {
+                Some(title) => f.write_fmt(format_args!("Unexpected HTML page: {}", title)),
+                None => f.write_str("Unexpected HTML page"),
+            },
+            BiomartError::Incomplete => f.write_str("Incomplete result, completion stamp missing"),
+            BiomartError::Xml { source, snippet } => f.write_fmt(format_args!(
+                "Failed parsing xml: {} in {:?}",
+                source, snippet
+            )),
+            BiomartError::Csv(e) => f.write_fmt(format_args!("Failed parsing tsv: {}", e)),
+            BiomartError::Record {
+                line,
+                column,
+                message,
+            } => {
+                f.write_str("Failed parsing record")?;
+                if let Some(line) = line {
+                    f.write_fmt(format_args!(" on line {}", line))?;
+                }
+                if let Some(column) = column {
+                    f.write_fmt(format_args!(" in column {:?}", column))?;
+                }
+                f.write_fmt(format_args!(": {}", message))
+            }
+            BiomartError::UnexpectedBody { expected, snippet } => f.write_fmt(format_args!(
+                "Unexpected response, expected {}: {:?}",
+                expected, snippet
+            )),
+            BiomartError::InvalidQuery(msg) => f.write_fmt(format_args!("Invalid query: {}", msg)),
+            BiomartError::Validation(issues) => {
+                f.write_str("Invalid query:")?;
+                for issue in issues {
+                    f.write_fmt(format_args!("\n  {}", issue))?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+const QUERY_ERROR_PREFIX: &str = "Query ERROR:";
+const EXCEPTION_PREFIX: &str = "BioMart::Exception::";
+
+/// BioMart reports most errors with status 200 and either a plain text
+/// `Query ERROR: …` message or an HTML page in place of the requested data.
+/// HTML pages are only accepted if `html` output was requested (see `Formatter::Html`).
+pub(crate) fn check_body(body: String, html: bool) -> Result<String, BiomartError> {
+    let head = body.trim_start();
+    if let Some(message) = head.strip_prefix(QUERY_ERROR_PREFIX) {
+        return Err(BiomartError::Server(ServerError::parse(message)));
+    }
+    if head.starts_with(EXCEPTION_PREFIX) {
+        return Err(BiomartError::Server(ServerError::parse(head)));
+    }
+    let lowercase = head.chars().take(64).collect::<String>().to_lowercase();
+    if !html && (lowercase.starts_with("<!doctype html") || lowercase.starts_with("<html")) {
+        return Err(BiomartError::UnexpectedHtml {
+            title: html_title(head),
+        });
+    }
+    Ok(body)
+}
+
+const SNIPPET_LENGTH: usize = 200;
+
+/// Truncates `body` for inclusion in error messages.
+pub(crate) fn snippet(body: &str) -> String {
+    match body.char_indices().nth(SNIPPET_LENGTH) {
+        Some((idx, _)) => format!("{}…", &body[..idx]),
+        None => body.to_string(),
+    }
+}
+
+fn html_title(html: &str) -> Option<String> {
+    let lowercase = html.to_lowercase();
+    let start = lowercase.find("<title>")? + "<title>".len();
+    let end = start + lowercase[start..].find("</title>")?;
+    Some(html[start..end].trim().to_string()).filter(|title| !title.is_empty())
+}
+
+impl From<reqwest::StatusCode> for BiomartError {
+    fn from(status: reqwest::StatusCode) -> Self {
+        BiomartError::Status {
+            status,
+            retry_after: None,
+        }
+    }
+}
+
+impl From<reqwest::Error> for BiomartError {
+    fn from(e: reqwest::Error) -> Self {
+        BiomartError::Transport(e)
+    }
+}
+
+impl From<csv::Error> for BiomartError {
+    fn from(e: csv::Error) -> Self {
+        BiomartError::Csv(e)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use reqwest::StatusCode;
+
+    use crate::error::check_body;
+    use crate::{BiomartError, ServerError};
+
+    #[test]
+    fn retryable_status() {
+        assert!(BiomartError::from(StatusCode::SERVICE_UNAVAILABLE).is_retryable());
+        assert!(BiomartError::from(StatusCode::TOO_MANY_REQUESTS).is_retryable());
+        assert!(!BiomartError::from(StatusCode::NOT_FOUND).is_retryable());
+        assert!(!BiomartError::InvalidQuery("no dataset".into()).is_retryable());
+    }
+
+    #[test]
+    fn detect_query_error() {
+        let body = "Query ERROR: caught BioMart::Exception::Usage: Filter foo NOT FOUND\n";
+        match check_body(body.into(), false) {
+            Err(BiomartError::Server(e)) => assert_eq!(
+                ServerError::new(
+                    Some("BioMart::Exception::Usage".into()),
+                    "Filter foo NOT FOUND"
+                ),
+                e
+            ),
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn detect_html_page() {
+        let body = "\n<!DOCTYPE html>\n<html><head><title>Ensembl is down for maintenance</title></head></html>";
+        match check_body(body.into(), false) {
+            Err(BiomartError::UnexpectedHtml { title }) => {
+                assert_eq!(Some("Ensembl is down for maintenance".into()), title)
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(check_body(body.into(), true).is_ok());
+        let tsv = "Gene stable ID\nENSG00000141510\n";
+        assert_eq!(tsv, check_body(tsv.into(), false).unwrap());
+    }
+}
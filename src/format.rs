@@ -0,0 +1,139 @@
+use getset::Getters;
+
+use crate::BiomartError;
+
+/// Output format of a query result, see `QueryBuilder::formatter`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Formatter {
+    /// Tab-separated values (the default).
+    #[default]
+    Tsv,
+    /// Comma-separated values, with quoted fields.
+    Csv,
+    /// An HTML table.
+    Html,
+    /// A binary Excel spreadsheet, which can only be retrieved via `MartClient::query_to_writer`.
+    Xls,
+    /// Sequences, requested by a sequence attribute such as `peptide` or `cdna`.
+    Fasta,
+    /// Gene annotations in the Gene Transfer Format, without header line.
+    Gtf,
+}
+
+impl Formatter {
+    /// Name of the formatter in the query XML, e.g. `"TSV"`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Formatter::Tsv => "TSV",
+            Formatter::Csv => "CSV",
+            Formatter::Html => "HTML",
+            Formatter::Xls => "XLS",
+            Formatter::Fasta => "FASTA",
+            Formatter::Gtf => "GTF",
+        }
+    }
+
+    /// Parses the name of a formatter in the query XML, ignoring case.
+    pub fn from_name(name: &str) -> Option<Self> {
+        [
+            Formatter::Tsv,
+            Formatter::Csv,
+            Formatter::Html,
+            Formatter::Xls,
+            Formatter::Fasta,
+            Formatter::Gtf,
+        ]
+        .iter()
+        .copied()
+        .find(|formatter| formatter.name().eq_ignore_ascii_case(name))
+    }
+
+    /// Field delimiter of tabular formats.
+    pub(crate) fn delimiter(self) -> Option<u8> {
+        match self {
+            Formatter::Tsv | Formatter::Gtf => Some(b'\t'),
+            Formatter::Csv => Some(b','),
+            _ => None,
+        }
+    }
+
+    /// Whether results in this format may start with a header line.
+    pub(crate) fn has_header(self) -> bool {
+        matches!(self, Formatter::Tsv | Formatter::Csv)
+    }
+}
+
+/// A sequence of a FASTA result.
+#[derive(Debug, Clone, PartialEq, Getters)]
+#[getset(get = "pub")]
+pub struct FastaRecord {
+    /// The header line without the leading `>`.
+    header: String,
+    sequence: String,
+}
+
+impl FastaRecord {
+    pub(crate) fn new(header: String, sequence: String) -> Self {
+        FastaRecord { header, sequence }
+    }
+
+    /// Values of the attributes requested along with the sequence, which BioMart separates by `|`.
+    pub fn header_fields(&self) -> Vec<&str> {
+        self.header.split('|').collect()
+    }
+}
+
+/// Splits a FASTA result into records, joining sequences wrapped over several lines.
+pub(crate) fn parse_fasta(raw: &str) -> Result<Vec<FastaRecord>, BiomartError> {
+    let mut records: Vec<FastaRecord> = vec![];
+    for (i, line) in raw.lines().enumerate() {
+        let line = line.trim_end();
+        if let Some(header) = line.strip_prefix('>') {
+            records.push(FastaRecord {
+                header: header.to_string(),
+                sequence: String::new(),
+            });
+        } else if line.is_empty() {
+            continue;
+        } else if let Some(record) = records.last_mut() {
+            record.sequence.push_str(line);
+        } else {
+            return Err(BiomartError::Record {
+                line: Some(i as u64 + 1),
+                column: None,
+                message: "sequence without header line".into(),
+            });
+        }
+    }
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::format::parse_fasta;
+    use crate::Formatter;
+
+    #[test]
+    fn formatter_names() {
+        assert_eq!("FASTA", Formatter::Fasta.name());
+        assert_eq!(Some(Formatter::Gtf), Formatter::from_name("gtf"));
+        assert_eq!(None, Formatter::from_name("JSON"));
+    }
+
+    #[test]
+    fn fasta_records() {
+        let raw = ">MEFGLSW|ENSG00000139618\nMPIGSKERPTFFEIFKTRCNKADLGPISLNWF\nEELSSEAPPYNSEPAEESEHKNNNYEPNLFK\n>SEQ2|ENSG00000141510\nMEEPQSDPSV\n";
+        let records = parse_fasta(raw).unwrap();
+        assert_eq!(2, records.len());
+        assert_eq!(
+            vec!["MEFGLSW", "ENSG00000139618"],
+            records[0].header_fields()
+        );
+        assert_eq!(
+            "MPIGSKERPTFFEIFKTRCNKADLGPISLNWFEELSSEAPPYNSEPAEESEHKNNNYEPNLFK",
+            records[0].sequence()
+        );
+        assert_eq!("MEEPQSDPSV", records[1].sequence());
+        assert!(parse_fasta("MEEPQSDPSV\n").is_err());
+    }
+}
@@ -1,42 +1,269 @@
-use std::error::Error;
-
 use csv::StringRecord;
 use getset::{Getters, MutGetters, Setters};
 use itertools::Itertools;
 use maplit::hashmap;
 use reqwest::blocking::Client;
-use serde::export::fmt::Debug;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use reqwest::{Certificate, Proxy};
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_with;
 use serde_with::CommaSeparator;
-use serde_xml_rs::from_reader;
 use xmltree::{Element, XMLNode};
 
-use crate::definitions::{bool_from_int, default_on_error_deserializer, StatusError};
+use crate::definitions::{bool_from_int, default_on_error_deserializer};
+use crate::error::check_body;
+use crate::format::parse_fasta;
+use crate::limit::Limiter;
+use crate::retry::retry_after;
+use crate::stream::from_io;
+use crate::validate::check_dataset;
+use std::collections::HashMap;
+use std::io::Write;
+use std::thread;
 use std::time::Duration;
 
+pub use crate::chunk::{ChunkProgress, Chunking};
+pub use crate::config::{
+    AttributeCollection, AttributeDescription, AttributeGroup, AttributePage, DatasetConfiguration,
+    FilterCollection, FilterDescription, FilterGroup, FilterOption, FilterPage, PushAction,
+};
+pub use crate::error::{BiomartError, ServerError};
+pub use crate::format::{FastaRecord, Formatter};
+pub use crate::region::{Region, Strand};
+pub use crate::retry::{RetryAttempt, RetryPolicy};
+pub use crate::sequence::{SequenceQuery, SequenceType};
+pub use crate::stream::{FastaRecords, QueryReader, QueryRecords};
+pub use crate::validate::ValidationIssue;
+
+#[cfg(feature = "async")]
+pub use crate::async_client::AsyncMartClient;
+#[cfg(feature = "async")]
+use crate::limit::AsyncLimiter;
+
+#[cfg(feature = "async")]
+mod async_client;
+mod chunk;
+mod config;
 mod definitions;
+mod error;
+mod format;
+mod limit;
+mod page;
+mod parse;
+mod region;
+mod retry;
+mod sequence;
+mod stream;
+mod validate;
 
 const REQUEST_ID: &str = "rust-biomart";
+const COMPLETION_STAMP: &str = "[success]";
 
 pub struct MartClient {
     server: String,
     client: Client,
+    retry_policy: RetryPolicy,
+    limiter: Limiter,
 }
 
-impl MartClient {
+/// Applies the HTTP settings of a `MartClientBuilder` to a (blocking or async) reqwest `ClientBuilder`.
+macro_rules! configure {
+    ($settings:expr, $builder:expr) => {{
+        let settings = $settings;
+        let mut builder = $builder
+            .gzip(true)
+            .default_headers(settings.headers.clone());
+        if let Some(timeout) = settings.connect_timeout {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(user_agent) = &settings.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        for proxy in &settings.proxies {
+            builder = builder.proxy(proxy.clone());
+        }
+        for certificate in &settings.root_certificates {
+            builder = builder.add_root_certificate(certificate.clone());
+        }
+        builder
+    }};
+}
+
+pub struct MartClientBuilder {
+    server: String,
+    timeout: Option<Duration>,
+    connect_timeout: Option<Duration>,
+    user_agent: Option<String>,
+    proxies: Vec<Proxy>,
+    headers: HeaderMap,
+    root_certificates: Vec<Certificate>,
+    client: Option<Client>,
+    retry_policy: RetryPolicy,
+    rate_limit: Option<(f64, u32)>,
+    max_in_flight: Option<usize>,
+}
+
+impl MartClientBuilder {
     pub fn new<S: Into<String>>(server: S) -> Self {
-        MartClient {
+        MartClientBuilder {
             server: server.into(),
-            client: Client::builder()
-                .timeout(Duration::from_secs(60))
-                .gzip(true)
-                .build()
-                .unwrap_or_else(|_| Client::new()),
+            timeout: Some(Duration::from_secs(60)),
+            connect_timeout: None,
+            user_agent: None,
+            proxies: vec![],
+            headers: HeaderMap::new(),
+            root_certificates: vec![],
+            client: None,
+            retry_policy: RetryPolicy::none(),
+            rate_limit: None,
+            max_in_flight: None,
+        }
+    }
+
+    /// Total timeout per request, `None` disables it. Defaults to 60 seconds.
+    pub fn timeout<T: Into<Option<Duration>>>(&mut self, timeout: T) -> &mut Self {
+        self.timeout = timeout.into();
+        self
+    }
+
+    /// Timeout for establishing a connection. Not set by default.
+    pub fn connect_timeout<T: Into<Option<Duration>>>(&mut self, timeout: T) -> &mut Self {
+        self.connect_timeout = timeout.into();
+        self
+    }
+
+    pub fn user_agent<S: Into<String>>(&mut self, user_agent: S) -> &mut Self {
+        self.user_agent = Some(user_agent.into());
+        self
+    }
+
+    /// Adds a proxy, e.g. `reqwest::Proxy::all("http://proxy.example.org:3128")`.
+    pub fn proxy(&mut self, proxy: Proxy) -> &mut Self {
+        self.proxies.push(proxy);
+        self
+    }
+
+    /// Adds a header that is sent with every request.
+    pub fn header(&mut self, name: HeaderName, value: HeaderValue) -> &mut Self {
+        self.headers.insert(name, value);
+        self
+    }
+
+    /// Adds a trusted root certificate, e.g. for mirrors behind an internal CA.
+    pub fn root_certificate(&mut self, certificate: Certificate) -> &mut Self {
+        self.root_certificates.push(certificate);
+        self
+    }
+
+    /// Uses a pre-configured client instead of building one.
+    /// All other HTTP settings of this builder are ignored in that case.
+    pub fn client(&mut self, client: Client) -> &mut Self {
+        self.client = Some(client);
+        self
+    }
+
+    /// Retries failed requests according to `policy`. By default, requests are not retried.
+    pub fn retry_policy(&mut self, policy: &RetryPolicy) -> &mut Self {
+        self.retry_policy = policy.clone();
+        self
+    }
+
+    /// Limits requests to `requests_per_second` on average, allowing bursts of up to `burst`
+    /// requests. Requests exceeding the limit wait until they may be sent.
+    /// The limit applies to all requests of the client, including those from other threads.
+    /// `build` fails if `requests_per_second` is not a positive number.
+    pub fn rate_limit(&mut self, requests_per_second: f64, burst: u32) -> &mut Self {
+        self.rate_limit = Some((requests_per_second, burst));
+        self
+    }
+
+    /// Limits the number of requests the client has in flight at the same time.
+    pub fn max_in_flight(&mut self, max_in_flight: usize) -> &mut Self {
+        self.max_in_flight = Some(max_in_flight);
+        self
+    }
+
+    pub fn build(&self) -> Result<MartClient, BiomartError> {
+        self.check_rate_limit()?;
+        let client = match &self.client {
+            Some(client) => client.clone(),
+            None => configure!(self, Client::builder().timeout(self.timeout)).build()?,
+        };
+        Ok(MartClient {
+            server: self.server.clone(),
+            client,
+            retry_policy: self.retry_policy.clone(),
+            limiter: Limiter::new(self.rate_limit, self.max_in_flight),
+        })
+    }
+
+    /// Builds an `AsyncMartClient` with the same settings.
+    /// A client set with `MartClientBuilder::client` is not used, since it is blocking.
+    #[cfg(feature = "async")]
+    pub fn build_async(&self) -> Result<AsyncMartClient, BiomartError> {
+        self.check_rate_limit()?;
+        let mut builder = reqwest::Client::builder();
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
         }
+        let client = configure!(self, builder).build()?;
+        let mut client = AsyncMartClient::from_client(self.server.clone(), client);
+        client.retry_policy = self.retry_policy.clone();
+        client.limiter = AsyncLimiter::new(self.rate_limit, self.max_in_flight);
+        Ok(client)
+    }
+
+    fn check_rate_limit(&self) -> Result<(), BiomartError> {
+        match self.rate_limit {
+            Some((rate, _)) if !(rate.is_finite() && rate > 0.) => {
+                Err(BiomartError::InvalidQuery(format!(
+                    "Rate limit must be a positive number of requests per second, got {}",
+                    rate
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+impl MartClient {
+    /// Creates a client for `server` with default settings (60s timeout, gzip).
+    ///
+    /// # Panics
+    ///
+    /// Panics if the underlying HTTP client cannot be initialized.
+    /// Use `MartClient::builder` to handle this case or to change the defaults.
+    pub fn new<S: Into<String>>(server: S) -> Self {
+        Self::builder(server)
+            .build()
+            .expect("Failed building HTTP client")
     }
 
-    fn make_request<T: Serialize + ?Sized>(&self, query: &T) -> Result<String, Box<dyn Error>> {
+    /// Creates a `MartClientBuilder` for `server`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::MartClient;
+    /// use std::time::Duration;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mart_client = MartClient::builder("http://ensembl.org:80/biomart/martservice")
+    ///     .timeout(Duration::from_secs(600))
+    ///     .connect_timeout(Duration::from_secs(10))
+    ///     .user_agent("my-pipeline/1.0")
+    ///     .build()?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn builder<S: Into<String>>(server: S) -> MartClientBuilder {
+        MartClientBuilder::new(server)
+    }
+
+    fn send<T: Serialize + ?Sized>(
+        &self,
+        query: &T,
+    ) -> Result<reqwest::blocking::Response, BiomartError> {
         let q = self
             .client
             .post(&self.server)
@@ -45,24 +272,300 @@ impl MartClient {
             .query(query);
         let response = q.send()?;
         if response.status().is_success() {
-            let text = response.text()?;
-            Ok(text)
+            Ok(response)
         } else {
-            Err(Box::new(StatusError(response.status())))
+            Err(BiomartError::Status {
+                status: response.status(),
+                retry_after: retry_after(response.headers()),
+            })
         }
     }
 
-    fn request_and_parse<P, R, T>(&self, query: &T, parser: P) -> Result<R, Box<dyn Error>>
+    fn make_request<T: Serialize + ?Sized>(&self, query: &T) -> Result<String, BiomartError> {
+        let _permit = self.limiter.acquire();
+        Ok(self.send(query)?.text()?)
+    }
+
+    fn retrying<F, R>(&self, retry_policy: &RetryPolicy, f: F) -> Result<R, BiomartError>
     where
-        P: FnOnce(String) -> Result<R, Box<dyn Error>>,
+        F: Fn() -> Result<R, BiomartError>,
+    {
+        let mut attempt = 1;
+        loop {
+            match f() {
+                Err(e) => match retry_policy.next_delay(attempt, &e) {
+                    Some(delay) => {
+                        thread::sleep(delay);
+                        attempt += 1;
+                    }
+                    None => return Err(e),
+                },
+                result => return result,
+            }
+        }
+    }
+
+    fn request_and_parse<P, R, T>(&self, query: &T, parser: P) -> Result<R, BiomartError>
+    where
+        P: Fn(String) -> Result<R, BiomartError>,
+        T: Serialize + ?Sized,
+    {
+        self.request_and_parse_with(&self.retry_policy, query, parser)
+    }
+
+    fn request_and_parse_with<P, R, T>(
+        &self,
+        retry_policy: &RetryPolicy,
+        query: &T,
+        parser: P,
+    ) -> Result<R, BiomartError>
+    where
+        P: Fn(String) -> Result<R, BiomartError>,
         T: Serialize + ?Sized,
     {
-        self.make_request(query).and_then(parser)
+        self.retrying(retry_policy, || {
+            self.make_request(query)
+                .and_then(|raw| check_body(raw, false))
+                .and_then(&parser)
+        })
     }
 
-    pub fn query(&self, query: &Query) -> Result<Response, Box<dyn Error>> {
+    /// Runs `query` and returns the raw result.
+    ///
+    /// Error messages BioMart embeds in the body of a successful response
+    /// (`Query ERROR: …`, HTML maintenance pages) are returned as `BiomartError::Server`
+    /// and `BiomartError::UnexpectedHtml` respectively.
+    /// If the query requests a completion stamp (the default, see `QueryBuilder::completion_stamp`),
+    /// the stamp is verified and removed from the result; a missing stamp yields
+    /// `BiomartError::Incomplete`.
+    ///
+    /// Binary results (`Formatter::Xls`) cannot be held in a `Response`;
+    /// use `query_to_writer` for those.
+    pub fn query(&self, query: &Query) -> Result<Response, BiomartError> {
+        self.query_with(&self.retry_policy, query)
+    }
+
+    fn query_with(
+        &self,
+        retry_policy: &RetryPolicy,
+        query: &Query,
+    ) -> Result<Response, BiomartError> {
+        if query.formatter() == Some(Formatter::Xls) {
+            return Err(BiomartError::InvalidQuery(
+                "XLS results are binary, use MartClient::query_to_writer to save them".into(),
+            ));
+        }
+        let s = query.to_string();
+        self.retrying(retry_policy, || {
+            self.make_request(&[("query", &s)])
+                .and_then(|raw| parse::query_result(raw, query))
+        })
+    }
+
+    /// Checks the filters and attributes of `query` against the metadata of its datasets in `mart`,
+    /// without running it. All problems found are returned as `BiomartError::Validation`,
+    /// with suggestions for misspelled names.
+    ///
+    /// Filters of a query read from XML are checked by their values only; use
+    /// `validate_builder` to also check the operations of typed filters such as `QueryBuilder::range`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::{BiomartError, MartClient, QueryBuilder};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+    /// let query = QueryBuilder::new()
+    ///     .dataset("hsapiens_gene_ensembl")
+    ///     .attribute("hgnc_symbl")
+    ///     .build();
+    /// match mart_client.validate("ENSEMBL_MART_ENSEMBL", &query) {
+    ///     Err(BiomartError::Validation(issues)) => {
+    ///         // Unknown attribute hgnc_symbl in hsapiens_gene_ensembl, did you mean hgnc_symbol?
+    ///         eprintln!("{}", issues[0]);
+    ///     }
+    ///     result => result?,
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn validate(&self, mart: &str, query: &Query) -> Result<(), BiomartError> {
+        self.validate_builder(mart, &QueryBuilder::from_query(query)?)
+    }
+
+    /// Like `validate`, but checks the filters as added to `query`, including whether
+    /// the datasets declare the operations of typed filters (e.g. `>=` for the `start`
+    /// filter of `QueryBuilder::range`, `id_list` for `QueryBuilder::filter_ids`).
+    pub fn validate_builder(&self, mart: &str, query: &QueryBuilder) -> Result<(), BiomartError> {
+        let mut issues = vec![];
+        for dataset in &query.datasets {
+            let filters = self.filters(mart, &dataset.name)?;
+            let attributes = self.attributes(mart, &dataset.name)?;
+            issues.extend(check_dataset(dataset, &filters, &attributes));
+        }
+        if issues.is_empty() {
+            Ok(())
+        } else {
+            Err(BiomartError::Validation(issues))
+        }
+    }
+
+    /// Runs a query whose attributes come from several attribute pages of `mart`,
+    /// which BioMart does not allow, as one query per page, and joins the results on
+    /// the attribute `key` (e.g. `"ensembl_gene_id"`), which must be available on each page.
+    ///
+    /// The result has `key` in its first column, followed by the other attributes in their
+    /// original order. Rows are repeated for multiple matches (e.g. several homologs of a gene).
+    /// Keys missing from some pages are kept (a full outer join), with empty fields for those pages.
+    /// Only TSV queries of a single dataset are supported.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::{MartClient, QueryBuilder};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+    /// let mut query = QueryBuilder::new();
+    /// query
+    ///     .dataset("hsapiens_gene_ensembl")
+    ///     .attributes(vec!["external_gene_name", "mmusculus_homolog_ensembl_gene"])
+    ///     .filter("ensembl_gene_id", vec!["ENSG00000141510"]);
+    /// let response = mart_client.query_by_page("ENSEMBL_MART_ENSEMBL", &query, "ensembl_gene_id")?;
+    /// println!("{:?}", response.column("mmusculus_homolog_ensembl_gene"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn query_by_page(
+        &self,
+        mart: &str,
+        query: &QueryBuilder,
+        key: &str,
+    ) -> Result<Response, BiomartError> {
+        let metadata = self.attributes(mart, &query.datasets[0].name)?;
+        let responses = page::split_by_page(query, &metadata, key)?
+            .iter()
+            .map(|q| self.query(q))
+            .collect::<Result<Vec<_>, _>>()?;
+        page::join(query, key, responses)
+    }
+
+    /// Returns the number of rows `query` would return, without retrieving them.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::{MartClient, QueryBuilder};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+    /// let query = QueryBuilder::new()
+    ///     .dataset("hsapiens_gene_ensembl")
+    ///     .attribute("ensembl_gene_id")
+    ///     .filter("chromosome_name", vec!["MT"])
+    ///     .build();
+    /// let genes = mart_client.count(&query)?;
+    /// assert!(genes > 0);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn count(&self, query: &Query) -> Result<u64, BiomartError> {
+        let s = query.as_count().to_string();
+        self.request_and_parse(&[("query", &s)], parse::count)
+    }
+
+    /// Runs `query` and returns its result as a stream of bytes read directly
+    /// from the HTTP response, without holding the whole result in memory.
+    ///
+    /// Errors the server reports at the beginning of the body are detected before returning.
+    /// The retry policy applies until the response starts; an `in_flight` slot
+    /// (see `MartClientBuilder::max_in_flight`) stays occupied until the reader is dropped.
+    pub fn query_reader(&self, query: &Query) -> Result<QueryReader, BiomartError> {
         let s = query.to_string();
-        self.request_and_parse(&[("query", &s)], |xml| Ok(Response { raw: xml }))
+        self.retrying(&self.retry_policy, || {
+            let permit = self.limiter.acquire();
+            let response = self.send(&[("query", &s)])?;
+            QueryReader::new(
+                response,
+                query.completion_stamp(),
+                query.formatter() == Some(Formatter::Html),
+                permit,
+            )
+        })
+    }
+
+    /// Runs `query` and returns an iterator over the rows of the result,
+    /// parsed while the body is being received. Only tabular formats (TSV, CSV, GTF) are supported.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::{MartClient, QueryBuilder};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+    /// let query = QueryBuilder::new()
+    ///     .dataset("hsapiens_gene_ensembl")
+    ///     .attributes(vec!["ensembl_gene_id", "chromosome_name"])
+    ///     .filter("chromosome_name", vec!["MT"])
+    ///     .build();
+    /// for record in mart_client.query_records(&query)? {
+    ///     let record = record?;
+    ///     println!("{}", &record[0]);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn query_records(&self, query: &Query) -> Result<QueryRecords, BiomartError> {
+        let formatter = query.tabular_formatter()?;
+        QueryRecords::new(
+            self.query_reader(query)?,
+            formatter,
+            query.has_header_line(),
+        )
+    }
+
+    /// Runs a query for sequences (see `SequenceQuery`) and returns an iterator
+    /// over the sequences, parsed while the body is being received.
+    pub fn sequences(&self, query: &Query) -> Result<FastaRecords, BiomartError> {
+        if query.formatter() != Some(Formatter::Fasta) {
+            return Err(BiomartError::InvalidQuery(
+                "The query does not use the FASTA formatter".into(),
+            ));
+        }
+        Ok(FastaRecords::new(self.query_reader(query)?))
+    }
+
+    /// Runs `query` and copies the raw result to `writer` without holding it in memory.
+    /// Returns the number of bytes written. Unlike `query`, this supports binary formats
+    /// such as `Formatter::Xls`.
+    pub fn query_to_writer<W: Write + ?Sized>(
+        &self,
+        query: &Query,
+        writer: &mut W,
+    ) -> Result<u64, BiomartError> {
+        let mut reader = self.query_reader(query)?;
+        std::io::copy(&mut reader, writer).map_err(from_io)
+    }
+
+    /// Runs `query` in batches, splitting the values of the filter given by `chunking`,
+    /// and concatenates the results into a single `Response` with one header line.
+    ///
+    /// BioMart tends to reject or time out on queries with thousands of filter values,
+    /// see `Chunking` for an example.
+    /// Batches may run in parallel (see `Chunking::workers`); the result is the same
+    /// as for a sequential run, with rows ordered by batch.
+    /// Only TSV, GTF and FASTA queries can be chunked; FASTA results are merged by sequence.
+    pub fn query_chunked(
+        &self,
+        query: &QueryBuilder,
+        chunking: &Chunking,
+    ) -> Result<Response, BiomartError> {
+        let queries = chunking.split(query)?;
+        let responses = chunking.run(queries, |q| {
+            self.query_with(
+                chunking.retry_policy.as_ref().unwrap_or(&self.retry_policy),
+                q,
+            )
+        })?;
+        Ok(chunking.merge(responses, query.build()))
     }
 
     /// Lists available marts for given registry.
@@ -79,12 +582,8 @@ impl MartClient {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn marts(&self) -> Result<Vec<MartInfo>, Box<dyn Error>> {
-        self.request_and_parse(&[("type", "registry")], |xml| {
-            let registry: MartRegistry = from_reader(xml.as_bytes())
-                .unwrap_or_else(|_| panic!("Failed parsing xml: {:?}", &xml));
-            Ok(registry.marts)
-        })
+    pub fn marts(&self) -> Result<Vec<MartInfo>, BiomartError> {
+        self.request_and_parse(&[("type", "registry")], parse::registry)
     }
 
     /// Lists available datasets for a given mart.
@@ -106,16 +605,8 @@ impl MartClient {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn datasets(&self, mart: &str) -> Result<Vec<DatasetInfo>, Box<dyn Error>> {
-        self.request_and_parse(&[("mart", mart), ("type", "datasets")], |xml| {
-            Ok(csv::ReaderBuilder::new()
-                .has_headers(false)
-                .delimiter(b'\t')
-                .from_reader(xml.trim().as_bytes())
-                .deserialize::<DatasetInfo>()
-                .filter_map(Result::ok)
-                .collect())
-        })
+    pub fn datasets(&self, mart: &str) -> Result<Vec<DatasetInfo>, BiomartError> {
+        self.request_and_parse(&[("mart", mart), ("type", "datasets")], parse::datasets)
     }
 
     /// Lists available filters for a given mart+dataset.
@@ -138,39 +629,10 @@ impl MartClient {
     /// # Ok(())
     /// # }
     /// ```
-    pub fn filters(&self, mart: &str, dataset: &str) -> Result<Vec<FilterInfo>, Box<dyn Error>> {
+    pub fn filters(&self, mart: &str, dataset: &str) -> Result<Vec<FilterInfo>, BiomartError> {
         self.request_and_parse(
             &[("mart", mart), ("dataset", dataset), ("type", "filters")],
-            |tsv| {
-                Ok(csv::ReaderBuilder::new()
-                    .has_headers(false)
-                    .delimiter(b'\t')
-                    .from_reader(tsv.trim().as_bytes())
-                    .deserialize::<FilterInfo>()
-                    .filter_map(Result::ok)
-                    // FIXME: write deserializer that can handle Vec<String> representations like "[v_1, v_2, …, v_n]"
-                    .map(|mut info| match info.options.len() {
-                        0 => info,
-                        1 => {
-                            let s: String = info.options[0]
-                                .trim_matches(|c| c == '[' || c == ']')
-                                .into();
-                            if !s.is_empty() {
-                                info.options[0] = s;
-                            } else {
-                                info.options.clear();
-                            }
-                            info
-                        }
-                        _ => {
-                            let n = info.options.len() - 1;
-                            info.options[0] = info.options[0].trim_matches('[').into();
-                            info.options[n] = info.options[n].trim_matches(']').into();
-                            info
-                        }
-                    })
-                    .collect())
-            },
+            parse::filters,
         )
     }
 
@@ -198,18 +660,46 @@ impl MartClient {
         &self,
         mart: &str,
         dataset: &str,
-    ) -> Result<Vec<AttributeInfo>, Box<dyn Error>> {
+    ) -> Result<Vec<AttributeInfo>, BiomartError> {
         self.request_and_parse(
             &[("mart", mart), ("dataset", dataset), ("type", "attributes")],
-            |tsv| {
-                Ok(csv::ReaderBuilder::new()
-                    .has_headers(false)
-                    .delimiter(b'\t')
-                    .from_reader(tsv.trim().as_bytes())
-                    .deserialize::<AttributeInfo>()
-                    .filter_map(Result::ok)
-                    .collect())
-            },
+            parse::attributes,
+        )
+    }
+
+    /// Retrieves the configuration of a dataset, i.e. its attribute pages, groups and
+    /// collections as well as its filters, including their options and filter containers.
+    ///
+    /// # Arguments
+    ///
+    /// * `mart` - Name of the Mart. See also `MartClient::name`.
+    /// * `dataset` - Name of the dataset. See also `DatasetInfo::dataset`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::MartClient;
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+    /// let config = mart_client.configuration("ENSEMBL_MART_ENSEMBL", "hsapiens_gene_ensembl")?;
+    /// for page in config.attribute_pages() {
+    ///     println!("{}: {} groups", page.display_name(), page.groups().len());
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn configuration(
+        &self,
+        mart: &str,
+        dataset: &str,
+    ) -> Result<DatasetConfiguration, BiomartError> {
+        self.request_and_parse(
+            &[
+                ("mart", mart),
+                ("dataset", dataset),
+                ("type", "configuration"),
+            ],
+            parse::configuration,
         )
     }
 }
@@ -217,6 +707,7 @@ impl MartClient {
 #[derive(Debug)]
 pub struct Response {
     raw: String,
+    query: Query,
 }
 
 impl Response {
@@ -224,23 +715,253 @@ impl Response {
         &self.raw
     }
 
+    /// The query that produced this response.
+    pub fn query(&self) -> &Query {
+        &self.query
+    }
+
+    /// Internal names of the attributes in the order of the columns (e.g. `"entrezgene_id"`),
+    /// as opposed to the display names in the header line (e.g. `"NCBI gene ID"`).
+    /// Attributes requested from both datasets of a linked query are qualified
+    /// by their dataset, e.g. `"mmusculus_gene_ensembl.ensembl_gene_id"`.
+    pub fn attributes(&self) -> Vec<String> {
+        let columns = self.query.dataset_attribute_names();
+        columns
+            .iter()
+            .map(|(dataset, name)| {
+                if columns.iter().filter(|(_, other)| other == name).count() > 1 {
+                    format!("{}.{}", dataset, name)
+                } else {
+                    name.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// Index of the column holding the values of `attribute`, given by its internal name
+    /// as listed by `attributes`, or qualified by its dataset (`"dataset.attribute"`).
+    /// Returns `None` for names requested from both datasets without qualification.
+    pub fn column_index(&self, attribute: &str) -> Option<usize> {
+        self.attributes()
+            .iter()
+            .position(|name| name == attribute)
+            .or_else(|| {
+                self.query
+                    .dataset_attribute_names()
+                    .iter()
+                    .position(|(dataset, name)| format!("{}.{}", dataset, name) == attribute)
+            })
+    }
+
+    /// Values of the column holding `attribute`, given by its internal name,
+    /// independent of the header text.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::{MartClient, QueryBuilder};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+    /// let query = QueryBuilder::new()
+    ///     .dataset("hsapiens_gene_ensembl")
+    ///     .attributes(vec!["affy_hg_u133_plus_2", "entrezgene_id"])
+    ///     .filter("affy_hg_u133_plus_2", vec!["202763_at"])
+    ///     .build();
+    /// let response = mart_client.query(&query)?;
+    /// assert_eq!(Some(vec!["836".to_string()]), response.column("entrezgene_id"));
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn column(&self, attribute: &str) -> Option<Vec<String>> {
+        let index = self.column_index(attribute)?;
+        Some(
+            self.records()
+                .iter()
+                .map(|record| record.get(index).unwrap_or_default().to_string())
+                .collect(),
+        )
+    }
+
+    /// Deserializes each row into a `T`, matching columns to fields by the display names
+    /// in the header line (e.g. `"NCBI gene ID"`). Empty cells deserialize to `None` for `Option` fields.
+    /// Without a header line (see `QueryBuilder::header`), use `deserialize_by_attribute`.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::{MartClient, QueryBuilder};
+    /// use serde::Deserialize;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct Probe {
+    ///     #[serde(rename = "AFFY HG U133 Plus 2 probe")]
+    ///     probe: String,
+    ///     #[serde(rename = "NCBI gene ID")]
+    ///     entrez_id: Option<u64>,
+    /// }
+    ///
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+    /// let query = QueryBuilder::new()
+    ///     .dataset("hsapiens_gene_ensembl")
+    ///     .attributes(vec!["affy_hg_u133_plus_2", "entrezgene_id"])
+    ///     .filter("affy_hg_u133_plus_2", vec!["202763_at"])
+    ///     .build();
+    /// let probes: Vec<Probe> = mart_client.query(&query)?.deserialize()?;
+    /// assert_eq!(Some(836), probes[0].entrez_id);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn deserialize<T: DeserializeOwned>(&self) -> Result<Vec<T>, BiomartError> {
+        let header = self.header().unwrap_or_default();
+        self.deserialize_with_header(header)
+    }
+
+    /// Deserializes each row into a `T`, matching columns to fields by the internal names
+    /// of the attributes in the query (e.g. `"entrezgene_id"`), which unlike display names
+    /// are unique and stable across releases.
+    pub fn deserialize_by_attribute<T: DeserializeOwned>(&self) -> Result<Vec<T>, BiomartError> {
+        let header = StringRecord::from(self.attributes());
+        self.deserialize_with_header(header)
+    }
+
+    fn deserialize_with_header<T: DeserializeOwned>(
+        &self,
+        header: StringRecord,
+    ) -> Result<Vec<T>, BiomartError> {
+        // deserialize record by record, as the reader ignores replaced headers without a header line
+        self.reader()?
+            .records()
+            .map(|record| {
+                let record = record.map_err(|e| record_error(e, &header))?;
+                record.deserialize(Some(&header)).map_err(|e| {
+                    let mut error = record_error(e, &header);
+                    if let BiomartError::Record { line, .. } = &mut error {
+                        *line = record.position().map(|pos| pos.line());
+                    }
+                    error
+                })
+            })
+            .collect()
+    }
+
+    fn reader(&self) -> Result<csv::Reader<&[u8]>, BiomartError> {
+        let formatter = self.query.tabular_formatter()?;
+        Ok(reader_builder(formatter)
+            .has_headers(self.query.has_header_line())
+            .from_reader(self.raw.as_bytes()))
+    }
+
+    /// The header line, or `None` if it cannot be parsed. See `try_header`.
     pub fn header(&self) -> Option<StringRecord> {
-        csv::ReaderBuilder::new()
-            .delimiter(b'\t')
-            .from_reader(self.raw.as_bytes())
+        self.try_header().ok()
+    }
+
+    /// The header line, or an error naming the line that could not be parsed.
+    /// Fails for formats that are not tabular or have no header line, see `Formatter`.
+    pub fn try_header(&self) -> Result<StringRecord, BiomartError> {
+        if !self.query.has_header_line() {
+            return Err(BiomartError::InvalidQuery(
+                "The result has no header line".into(),
+            ));
+        }
+        self.reader()?
             .headers()
-            .ok()
             .cloned()
+            .map_err(|e| record_error(e, &StringRecord::new()))
     }
 
+    /// All rows below the header line, silently dropping those that cannot be parsed
+    /// (e.g. because of a tab or line break within a value). See `try_records` and `records_lossless`.
     pub fn records(&self) -> Vec<StringRecord> {
-        csv::ReaderBuilder::new()
-            .delimiter(b'\t')
-            .from_reader(self.raw.as_bytes())
+        self.records_lossless().0
+    }
+
+    /// All rows below the header line, or the first error with its line number.
+    /// Rows with a different number of fields than the header line are errors.
+    pub fn try_records(&self) -> Result<Vec<StringRecord>, BiomartError> {
+        self.reader()?
             .records()
-            .filter_map(Result::ok)
+            .map(|record| record.map_err(|e| record_error(e, &StringRecord::new())))
             .collect()
     }
+
+    /// All rows below the header line that can be parsed, together with an error
+    /// for each skipped row telling its line number and why it was skipped.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::{MartClient, QueryBuilder};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+    /// let query = QueryBuilder::new()
+    ///     .dataset("hsapiens_gene_ensembl")
+    ///     .attributes(vec!["ensembl_gene_id", "description"])
+    ///     .filter("ensembl_gene_id", vec!["ENSG00000141510"])
+    ///     .build();
+    /// let (records, skipped) = mart_client.query(&query)?.records_lossless();
+    /// for error in &skipped {
+    ///     eprintln!("skipped row: {}", error);
+    /// }
+    /// assert_eq!(1, records.len() + skipped.len());
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn records_lossless(&self) -> (Vec<StringRecord>, Vec<BiomartError>) {
+        let mut records = vec![];
+        let mut skipped = vec![];
+        let mut reader = match self.reader() {
+            Ok(reader) => reader,
+            Err(e) => return (records, vec![e]),
+        };
+        for record in reader.records() {
+            match record {
+                Ok(record) => records.push(record),
+                Err(e) => skipped.push(record_error(e, &StringRecord::new())),
+            }
+        }
+        (records, skipped)
+    }
+
+    /// The sequences of a result in `Formatter::Fasta`.
+    pub fn fasta(&self) -> Result<Vec<FastaRecord>, BiomartError> {
+        if self.query.formatter() != Some(Formatter::Fasta) {
+            return Err(BiomartError::InvalidQuery(
+                "The query does not use the FASTA formatter".into(),
+            ));
+        }
+        parse_fasta(&self.raw)
+    }
+}
+
+/// Reader settings for tabular results. BioMart only quotes fields in CSV,
+/// so quotes inside TSV values are taken literally.
+pub(crate) fn reader_builder(formatter: Formatter) -> csv::ReaderBuilder {
+    let mut builder = csv::ReaderBuilder::new();
+    builder
+        .delimiter(formatter.delimiter().unwrap_or(b'\t'))
+        .quoting(formatter == Formatter::Csv);
+    builder
+}
+
+/// Converts a csv error into a `BiomartError::Record` naming the offending line and column.
+fn record_error(e: csv::Error, header: &StringRecord) -> BiomartError {
+    let line = e.position().map(|pos| pos.line());
+    let (column, message) = match e.kind() {
+        csv::ErrorKind::Deserialize { err, .. } => (
+            err.field()
+                .and_then(|i| header.get(i as usize))
+                .map(String::from),
+            err.kind().to_string(),
+        ),
+        _ => (None, e.to_string()),
+    };
+    BiomartError::Record {
+        line,
+        column,
+        message,
+    }
 }
 
 #[derive(Debug, Deserialize, Getters, Setters, MutGetters)]
@@ -310,8 +1031,11 @@ pub struct MartRegistry {
 #[serde(rename = "MartURLLocation")]
 #[getset(set = "pub", get = "pub", get_mut = "pub")]
 pub struct MartInfo {
+    #[serde(default)]
     host: String,
+    #[serde(default, deserialize_with = "default_on_error_deserializer")]
     port: usize,
+    #[serde(default)]
     database: String,
     #[serde(
         default,
@@ -324,28 +1048,280 @@ pub struct MartInfo {
     mart_user: String,
     #[serde(default, deserialize_with = "default_on_error_deserializer")]
     default: bool,
+    #[serde(default, deserialize_with = "default_on_error_deserializer")]
+    redirect: bool,
+    #[serde(default = "default_virtual_schema")]
     server_virtual_schema: String,
+    #[serde(default)]
     display_name: String,
+    #[serde(default)]
     path: String,
     name: String,
 }
 
-enum FilterOperation {
-    Match(Vec<String>),
-    Include,
-    Exclude,
-}
+fn default_virtual_schema() -> String {
+    "default".into()
+}
+
+#[derive(Clone)]
+enum FilterOperation {
+    Match(Vec<String>),
+    /// Values of a filter of type `id_list`.
+    IdList(Vec<String>),
+    Text(String),
+    /// Value of a filter expected to be declared with operation `>=`, like `start`.
+    /// The comparison is defined by the server, so this is only checked by validation.
+    AtLeast(String),
+    /// Value of a filter expected to be declared with operation `<=`, like `end`.
+    AtMost(String),
+    /// Boolean filter, `only`.
+    Include,
+    /// Boolean filter, `excluded`.
+    Exclude,
+}
+
+impl FilterOperation {
+    /// The `value` of the filter element, or `None` for boolean filters.
+    fn value(&self) -> Option<String> {
+        match self {
+            FilterOperation::Match(values) | FilterOperation::IdList(values) => {
+                Some(values.iter().join(","))
+            }
+            FilterOperation::Text(value)
+            | FilterOperation::AtLeast(value)
+            | FilterOperation::AtMost(value) => Some(value.clone()),
+            FilterOperation::Include | FilterOperation::Exclude => None,
+        }
+    }
+
+    /// The operation as listed in `FilterInfo::operation`, for those that have to be declared.
+    fn operator(&self) -> Option<&'static str> {
+        match self {
+            FilterOperation::AtLeast(_) => Some(">="),
+            FilterOperation::AtMost(_) => Some("<="),
+            FilterOperation::Include => Some("only"),
+            FilterOperation::Exclude => Some("excluded"),
+            _ => None,
+        }
+    }
+}
+
+/// Filters and attributes of one `<Dataset>` element of a query.
+#[derive(Clone, Default)]
+struct DatasetQuery {
+    name: String,
+    filters: Vec<(String, FilterOperation)>,
+    attributes: Vec<String>,
+    /// Further XML attributes of the element, e.g. `interface`, kept for round-tripping.
+    extra: HashMap<String, String>,
+}
+
+impl DatasetQuery {
+    fn from_element(element: &Element) -> Result<Self, BiomartError> {
+        let mut dataset = DatasetQuery {
+            extra: element.attributes.clone(),
+            ..Default::default()
+        };
+        dataset.name = dataset.extra.remove("name").unwrap_or_default();
+        for child in element.children.iter().filter_map(XMLNode::as_element) {
+            let name = child.attributes.get("name").cloned().ok_or_else(|| {
+                BiomartError::InvalidQuery(format!("{} element without name", child.name))
+            })?;
+            match child.name.as_str() {
+                "Attribute" => dataset.attributes.push(name),
+                "Filter" => {
+                    let operation = match (
+                        child.attributes.get("value"),
+                        child.attributes.get("excluded").map(String::as_str),
+                    ) {
+                        (Some(values), _) => {
+                            FilterOperation::Match(values.split(',').map(String::from).collect())
+                        }
+                        (None, Some("1")) => FilterOperation::Exclude,
+                        (None, Some(_)) => FilterOperation::Include,
+                        (None, None) => {
+                            return Err(BiomartError::InvalidQuery(format!(
+                                "Filter {} has neither value nor excluded flag",
+                                name
+                            )))
+                        }
+                    };
+                    dataset.filters.push((name, operation));
+                }
+                other => {
+                    return Err(BiomartError::InvalidQuery(format!(
+                        "Unexpected element {} in dataset {}",
+                        other, dataset.name
+                    )))
+                }
+            }
+        }
+        Ok(dataset)
+    }
+
+    fn build(&self) -> Element {
+        let mut dataset = Element::new("Dataset");
+        dataset.attributes = self.extra.clone();
+        dataset.attributes.insert("name".into(), self.name.clone());
+        for (filter, values) in &self.filters {
+            let attributes = match (values, values.value()) {
+                (_, Some(s)) => {
+                    hashmap! {"name".into() => filter.to_string(), "value".into() => s}
+                }
+                (FilterOperation::Exclude, None) => {
+                    hashmap! {"name".into() => filter.to_string(), "excluded".into() => "1".into()}
+                }
+                (_, None) => {
+                    hashmap! {"name".into() => filter.to_string(), "excluded".into() => "0".into()}
+                }
+            };
+
+            dataset.children.push(XMLNode::Element(Element {
+                prefix: None,
+                namespace: None,
+                namespaces: None,
+                name: "Filter".into(),
+                attributes,
+                children: vec![],
+            }))
+        }
+        for attribute in &self.attributes {
+            dataset.children.push(XMLNode::Element(Element {
+                prefix: None,
+                namespace: None,
+                namespaces: None,
+                name: "Attribute".into(),
+                attributes: hashmap! {"name".into() => attribute.to_string()},
+                children: vec![],
+            }))
+        }
+        dataset
+    }
+}
+
+#[derive(Clone)]
+pub struct QueryBuilder {
+    mart: String,
+    /// The main dataset, optionally followed by a linked one.
+    datasets: Vec<DatasetQuery>,
+    /// Index of the dataset that filters and attributes are added to.
+    current: usize,
+    completion_stamp: bool,
+    formatter: Formatter,
+    virtual_schema_name: String,
+    unique_rows: bool,
+    header: bool,
+    limit: Option<usize>,
+    request_id: Option<String>,
+    /// Further XML attributes of the query, e.g. `datasetConfigVersion`, kept for round-tripping.
+    extra: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Query {
+    inner: Element,
+}
+
+impl Query {
+    /// Parses a query in BioMart's XML format, e.g. as exported by the web interface.
+    /// Use `QueryBuilder::from_query` to modify it.
+    pub fn from_xml(xml: &str) -> Result<Self, BiomartError> {
+        let inner = Element::parse(xml.trim().as_bytes())
+            .map_err(|e| BiomartError::InvalidQuery(format!("Malformed query XML: {}", e)))?;
+        if inner.name != "Query" {
+            return Err(BiomartError::InvalidQuery(format!(
+                "Expected a Query element, found {}",
+                inner.name
+            )));
+        }
+        Ok(Query { inner })
+    }
+
+    /// Internal names of the requested attributes, in the order of the result columns.
+    pub fn attribute_names(&self) -> Vec<String> {
+        self.dataset_attribute_names()
+            .into_iter()
+            .map(|(_, name)| name)
+            .collect()
+    }
+
+    /// Names of the requested attributes along with the names of their datasets.
+    fn dataset_attribute_names(&self) -> Vec<(String, String)> {
+        self.inner
+            .children
+            .iter()
+            .filter_map(XMLNode::as_element)
+            .filter(|e| e.name == "Dataset")
+            .flat_map(|dataset| {
+                let name = dataset.attributes.get("name").cloned().unwrap_or_default();
+                dataset
+                    .children
+                    .iter()
+                    .filter_map(XMLNode::as_element)
+                    .filter(|e| e.name == "Attribute")
+                    .filter_map(move |e| Some((name.clone(), e.attributes.get("name")?.clone())))
+            })
+            .collect()
+    }
+
+    /// Whether the result starts with a header line.
+    pub fn header(&self) -> bool {
+        self.inner.attributes.get("header").map(String::as_str) == Some("1")
+    }
+
+    /// Output format of the result, or `None` if the formatter is unknown to this crate.
+    pub fn formatter(&self) -> Option<Formatter> {
+        self.inner
+            .attributes
+            .get("formatter")
+            .and_then(|name| Formatter::from_name(name))
+    }
 
-pub struct QueryBuilder {
-    mart: String,
-    dataset: String,
-    filters: Vec<(String, FilterOperation)>,
-    attributes: Vec<String>,
-}
+    /// Formatter of results that can be parsed as records.
+    pub(crate) fn tabular_formatter(&self) -> Result<Formatter, BiomartError> {
+        match self.formatter() {
+            Some(formatter) if formatter.delimiter().is_some() => Ok(formatter),
+            formatter => Err(BiomartError::InvalidQuery(format!(
+                "Results in {} format cannot be parsed as records",
+                formatter.map_or("an unknown", Formatter::name)
+            ))),
+        }
+    }
 
-#[derive(Debug)]
-pub struct Query {
-    inner: Element,
+    /// Whether the result actually starts with a header line, which depends on the formatter.
+    pub(crate) fn has_header_line(&self) -> bool {
+        self.header()
+            && match self.formatter() {
+                Some(formatter) => formatter.has_header(),
+                None => true,
+            }
+    }
+
+    /// A copy of this query asking for the number of rows instead of the rows themselves.
+    pub(crate) fn as_count(&self) -> Query {
+        let mut query = self.clone();
+        query.inner.attributes.remove("limitSize");
+        for (name, value) in &[
+            ("count", "1"),
+            ("completionStamp", "0"),
+            ("formatter", "TSV"),
+        ] {
+            query
+                .inner
+                .attributes
+                .insert(name.to_string(), value.to_string());
+        }
+        query
+    }
+
+    /// Whether the server is asked to append `[success]` to a complete result.
+    pub fn completion_stamp(&self) -> bool {
+        self.inner
+            .attributes
+            .get("completionStamp")
+            .map(String::as_str)
+            == Some("1")
+    }
 }
 
 impl ToString for Query {
@@ -375,7 +1351,7 @@ impl Default for Query {
             </Query>"##,
             requestid = REQUEST_ID
         );
-        let inner = Element::parse(data.as_bytes()).unwrap();
+        let inner = Element::parse(data.trim().as_bytes()).unwrap();
         Query { inner }
     }
 }
@@ -384,9 +1360,16 @@ impl Default for QueryBuilder {
     fn default() -> Self {
         QueryBuilder {
             mart: "".into(),
-            dataset: "".into(),
-            filters: vec![],
-            attributes: vec![],
+            datasets: vec![DatasetQuery::default()],
+            current: 0,
+            completion_stamp: true,
+            formatter: Formatter::default(),
+            virtual_schema_name: "default".into(),
+            unique_rows: true,
+            header: true,
+            limit: None,
+            request_id: Some(REQUEST_ID.into()),
+            extra: HashMap::new(),
         }
     }
 }
@@ -401,18 +1384,60 @@ impl QueryBuilder {
         self
     }
 
+    /// Sets the main dataset, to which subsequent filters and attributes are added.
     pub fn dataset<S: Into<String>>(&mut self, dataset: S) -> &mut Self {
-        self.dataset = dataset.into();
+        self.datasets[0].name = dataset.into();
+        self.current = 0;
+        self
+    }
+
+    /// Links a second dataset to the main one, to which subsequent filters and attributes are added
+    /// (use `dataset` to switch back). BioMart joins the datasets on their common key,
+    /// e.g. human genes with their mouse homologs. A query can link at most one dataset,
+    /// so calling this again replaces the name of the linked dataset.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::{MartClient, QueryBuilder};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+    /// let query = QueryBuilder::new()
+    ///     .dataset("hsapiens_gene_ensembl")
+    ///     .attribute("ensembl_gene_id")
+    ///     .filter("ensembl_gene_id", vec!["ENSG00000141510"])
+    ///     .linked_dataset("mmusculus_gene_ensembl")
+    ///     .attribute("ensembl_gene_id")
+    ///     .build();
+    /// let response = mart_client.query(&query)?;
+    /// for record in response.records() {
+    ///     println!("human {} - mouse {}", &record[0], &record[1]);
+    /// }
+    /// // attributes requested from both datasets are qualified by their dataset
+    /// let mouse_ids = response.column("mmusculus_gene_ensembl.ensembl_gene_id");
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn linked_dataset<S: Into<String>>(&mut self, dataset: S) -> &mut Self {
+        if self.datasets.len() < 2 {
+            self.datasets.push(DatasetQuery::default());
+        }
+        self.current = 1;
+        self.datasets[1].name = dataset.into();
         self
     }
 
+    fn current_dataset(&mut self) -> &mut DatasetQuery {
+        &mut self.datasets[self.current]
+    }
+
     pub fn filter<T, S, I>(&mut self, filter: S, values: I) -> &mut Self
     where
         T: Into<String>,
         S: Into<String>,
         I: IntoIterator<Item = T>,
     {
-        self.filters.push((
+        self.current_dataset().filters.push((
             filter.into(),
             FilterOperation::Match(values.into_iter().map(|s| s.into()).collect()),
         ));
@@ -420,7 +1445,7 @@ impl QueryBuilder {
     }
 
     pub fn filter_bool<S: Into<String>>(&mut self, filter: S, include: bool) -> &mut Self {
-        self.filters.push((
+        self.current_dataset().filters.push((
             filter.into(),
             if include {
                 FilterOperation::Include
@@ -431,8 +1456,63 @@ impl QueryBuilder {
         self
     }
 
+    /// Restricts the result to entries with the property of the boolean `filter`, e.g. `"with_hgnc"`.
+    pub fn filter_only<S: Into<String>>(&mut self, filter: S) -> &mut Self {
+        self.filter_bool(filter, true)
+    }
+
+    /// Restricts the result to entries without the property of the boolean `filter`.
+    pub fn filter_excluded<S: Into<String>>(&mut self, filter: S) -> &mut Self {
+        self.filter_bool(filter, false)
+    }
+
+    /// Filters by a list of identifiers, for filters of type `id_list` (e.g. `"ensembl_gene_id"`).
+    pub fn filter_ids<T, S, I>(&mut self, filter: S, ids: I) -> &mut Self
+    where
+        T: Into<String>,
+        S: Into<String>,
+        I: IntoIterator<Item = T>,
+    {
+        self.current_dataset().filters.push((
+            filter.into(),
+            FilterOperation::IdList(ids.into_iter().map(|s| s.into()).collect()),
+        ));
+        self
+    }
+
+    /// Filters by a single free-text value, for filters of type `text`.
+    pub fn filter_text<S: Into<String>, V: Into<String>>(
+        &mut self,
+        filter: S,
+        value: V,
+    ) -> &mut Self {
+        self.current_dataset()
+            .filters
+            .push((filter.into(), FilterOperation::Text(value.into())));
+        self
+    }
+
+    /// Restricts the result to features on `chromosome` overlapping the range from `start`
+    /// to `end` (1-based, inclusive), via the `chromosome_name`, `start` and `end` filters.
+    ///
+    /// How a filter compares its value is part of its definition on the server: in Ensembl
+    /// datasets, `start` keeps features ending at or after the value (operation `>=`) and
+    /// `end` keeps features starting at or before it (operation `<=`).
+    /// `MartClient::validate_builder` reports datasets declaring other operations.
+    pub fn range<S: Into<String>>(&mut self, chromosome: S, start: u64, end: u64) -> &mut Self {
+        self.filter("chromosome_name", vec![chromosome]);
+        let dataset = self.current_dataset();
+        dataset
+            .filters
+            .push(("start".into(), FilterOperation::AtLeast(start.to_string())));
+        dataset
+            .filters
+            .push(("end".into(), FilterOperation::AtMost(end.to_string())));
+        self
+    }
+
     pub fn attribute<S: Into<String>>(&mut self, attribute: S) -> &mut Self {
-        self.attributes.push(attribute.into());
+        self.current_dataset().attributes.push(attribute.into());
         self
     }
 
@@ -446,61 +1526,178 @@ impl QueryBuilder {
         self
     }
 
-    pub fn build(&self) -> Query {
-        let mut query = Query::default();
+    /// Whether the server should mark complete results with a trailing `[success]` line,
+    /// which lets `MartClient::query` detect truncated responses. Enabled by default.
+    pub fn completion_stamp(&mut self, enable: bool) -> &mut Self {
+        self.completion_stamp = enable;
+        self
+    }
 
-        query
-            .inner
-            .get_mut_child("Dataset")
-            .expect("dataset")
-            .attributes
-            .insert("name".into(), (&self.dataset).to_owned());
+    /// Output format of the result. Defaults to `Formatter::Tsv`.
+    pub fn formatter(&mut self, formatter: Formatter) -> &mut Self {
+        self.formatter = formatter;
+        self
+    }
 
-        for (filter, values) in &self.filters {
-            let v = query.inner.get_mut_child("Dataset").expect("dataset");
-            let attributes = match values {
-                FilterOperation::Match(values) => {
-                    let s: String = values.iter().join(",");
-                    hashmap! {"name".into() => filter.to_string(), "value".into() => s}
-                }
-                FilterOperation::Exclude => {
-                    hashmap! {"name".into() => filter.to_string(), "excluded".into() => "1".into()}
-                }
-                FilterOperation::Include => {
-                    hashmap! {"name".into() => filter.to_string(), "excluded".into() => "0".into()}
-                }
-            };
+    /// Virtual schema of the mart, see `MartInfo::server_virtual_schema`.
+    /// Defaults to `"default"`, which is wrong for some marts (e.g. Ensembl Plants).
+    pub fn virtual_schema_name<S: Into<String>>(&mut self, name: S) -> &mut Self {
+        self.virtual_schema_name = name.into();
+        self
+    }
 
-            v.children.push(XMLNode::Element(Element {
-                prefix: None,
-                namespace: None,
-                namespaces: None,
-                name: "Filter".into(),
-                attributes,
-                children: vec![],
-            }))
+    /// Whether duplicate rows are removed from the result. Enabled by default.
+    pub fn unique_rows(&mut self, unique: bool) -> &mut Self {
+        self.unique_rows = unique;
+        self
+    }
+
+    /// Whether the result starts with a header line of attribute display names. Enabled by default.
+    pub fn header(&mut self, header: bool) -> &mut Self {
+        self.header = header;
+        self
+    }
+
+    /// Maximum number of rows in the result.
+    pub fn limit<T: Into<Option<usize>>>(&mut self, limit: T) -> &mut Self {
+        self.limit = limit.into();
+        self
+    }
+
+    /// Identifies the client in the server logs. Defaults to `"rust-biomart"`.
+    pub fn request_id<S: Into<String>>(&mut self, request_id: S) -> &mut Self {
+        self.request_id = Some(request_id.into());
+        self
+    }
+
+    /// Builds the query and validates it with `MartClient::validate_builder`
+    /// against the metadata of the mart set via `mart`.
+    pub fn build_validated(&self, client: &MartClient) -> Result<Query, BiomartError> {
+        client.validate_builder(&self.mart, self)?;
+        Ok(self.build())
+    }
+
+    /// Creates a builder from an existing query, e.g. one parsed with `Query::from_xml`,
+    /// so that it can be modified. Building it again yields the same query, including
+    /// its request id or lack thereof.
+    ///
+    /// Count queries (`count="1"`) are rejected, since their result is not a table;
+    /// import the query without it and use `MartClient::count` instead.
+    ///
+    /// # Example
+    ///
+    /// ```
+    /// use rust_biomart::{Query, QueryBuilder};
+    /// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+    /// let exported = r#"<?xml version="1.0" encoding="UTF-8"?>
+    /// <!DOCTYPE Query>
+    /// <Query virtualSchemaName="default" formatter="TSV" header="0" uniqueRows="0" count="" datasetConfigVersion="0.6">
+    ///     <Dataset name="hsapiens_gene_ensembl" interface="default">
+    ///         <Filter name="chromosome_name" value="MT"/>
+    ///         <Attribute name="ensembl_gene_id"/>
+    ///     </Dataset>
+    /// </Query>"#;
+    /// let query = QueryBuilder::from_query(&Query::from_xml(exported)?)?
+    ///     .attribute("external_gene_name")
+    ///     .header(true)
+    ///     .build();
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn from_query(query: &Query) -> Result<Self, BiomartError> {
+        let mut builder = QueryBuilder::new();
+        let mut attributes = query.inner.attributes.clone();
+        if attributes.get("count").map(String::as_str) == Some("1") {
+            return Err(BiomartError::InvalidQuery(
+                "Count queries cannot be imported, use MartClient::count instead".into(),
+            ));
         }
-        for attribute in &self.attributes {
-            let v = query.inner.get_mut_child("Dataset").expect("dataset");
-            v.children.push(XMLNode::Element(Element {
-                prefix: None,
-                namespace: None,
-                namespaces: None,
-                name: "Attribute".into(),
-                attributes: hashmap! {"name".into() => attribute.to_string()},
-                children: vec![],
-            }))
+        let mut take_flag = |name: &str| attributes.remove(name).as_deref() == Some("1");
+        builder.completion_stamp = take_flag("completionStamp");
+        builder.unique_rows = take_flag("uniqueRows");
+        builder.header = take_flag("header");
+        if let Some(formatter) = attributes.remove("formatter") {
+            builder.formatter = Formatter::from_name(&formatter).ok_or_else(|| {
+                BiomartError::InvalidQuery(format!("Unknown formatter {}", formatter))
+            })?;
+        }
+        if let Some(name) = attributes.remove("virtualSchemaName") {
+            builder.virtual_schema_name = name;
+        }
+        builder.request_id = attributes.remove("requestid");
+        if let Some(limit) = attributes.remove("limitSize") {
+            builder.limit =
+                Some(limit.parse().map_err(|_| {
+                    BiomartError::InvalidQuery(format!("Invalid limitSize {}", limit))
+                })?);
+        }
+        builder.extra = attributes;
+
+        builder.datasets = query
+            .inner
+            .children
+            .iter()
+            .filter_map(XMLNode::as_element)
+            .filter(|e| e.name == "Dataset")
+            .map(DatasetQuery::from_element)
+            .collect::<Result<_, _>>()?;
+        match builder.datasets.len() {
+            0 => return Err(BiomartError::InvalidQuery("Query without dataset".into())),
+            1 | 2 => {}
+            n => {
+                return Err(BiomartError::InvalidQuery(format!(
+                    "Query with {} datasets, at most 2 can be linked",
+                    n
+                )))
+            }
         }
+        Ok(builder)
+    }
+
+    pub fn build(&self) -> Query {
+        let mut query = Query::default();
+        let flag = |enabled| if enabled { "1" } else { "0" }.to_string();
+
+        let attributes = &mut query.inner.attributes;
+        attributes.extend(self.extra.clone());
+        attributes.insert("formatter".into(), self.formatter.name().into());
+        attributes.insert("completionStamp".into(), flag(self.completion_stamp));
+        attributes.insert("virtualSchemaName".into(), self.virtual_schema_name.clone());
+        attributes.insert("uniqueRows".into(), flag(self.unique_rows));
+        attributes.insert("header".into(), flag(self.header));
+        match &self.request_id {
+            Some(request_id) => attributes.insert("requestid".into(), request_id.clone()),
+            None => attributes.remove("requestid"),
+        };
+        if let Some(limit) = self.limit {
+            attributes.insert("limitSize".into(), limit.to_string());
+        }
+
+        query.inner.children = self
+            .datasets
+            .iter()
+            .map(|dataset| XMLNode::Element(dataset.build()))
+            .collect();
         query
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::collections::HashMap;
+
     use itertools::Itertools;
+    use serde::Deserialize;
     use serde_xml_rs::from_reader;
+    use xmltree::XMLNode;
 
-    use crate::{MartClient, MartInfo, MartRegistry, QueryBuilder};
+    use crate::{
+        parse::{self, strip_completion_stamp},
+        BiomartError, Formatter, MartClient, MartInfo, MartRegistry, Query, QueryBuilder, Response,
+        ValidationIssue,
+    };
+    use reqwest::header::{HeaderValue, USER_AGENT};
+    use std::time::Duration;
 
     #[test]
     fn it_works() {
@@ -562,6 +1759,7 @@ mod tests {
                 visible: true,
                 mart_user: "".to_string(),
                 default: true,
+                redirect: false,
                 server_virtual_schema: "default".to_string(),
                 display_name: "Ensembl Genes 99".to_string(),
                 path: "/biomart/martservice".to_string(),
@@ -570,4 +1768,453 @@ mod tests {
         };
         assert_eq!(expected, registry);
     }
+
+    #[test]
+    fn parse_marts_lenient() {
+        let data = r##"<MartRegistry>
+    <MartURLLocation database="plants_mart_46" default="1" displayName="Ensembl Plants Genes 46" host="plants.ensembl.org" includeDatasets="" martUser="" name="plants_mart" path="/biomart/martservice" port="443" redirect="1" visible="1" extra="ignored" />
+    <MartURLLocation host="www.ensembl.org" name="ENSEMBL_MART_SNP" port="" />
+</MartRegistry>"##;
+        let registry: MartRegistry = from_reader(data.as_bytes()).unwrap();
+        assert_eq!(2, registry.marts.len());
+        assert!(registry.marts[0].redirect);
+        assert_eq!("default", registry.marts[0].server_virtual_schema);
+        assert_eq!("ENSEMBL_MART_SNP", registry.marts[1].name);
+        assert_eq!(0, registry.marts[1].port);
+        assert_eq!("", registry.marts[1].display_name);
+    }
+
+    #[test]
+    fn parse_metadata() {
+        let datasets = parse::datasets(
+            "\nTableSet\thsapiens_gene_ensembl\tHuman genes (GRCh38.p13)\t1\tGRCh38.p13\t200\t40000\tdefault\t2020-01-01\n\n"
+                .into(),
+        )
+        .unwrap();
+        assert_eq!("hsapiens_gene_ensembl", datasets[0].dataset());
+        // a server with a different number of columns is reported instead of yielding no rows
+        let changed = parse::attributes("ensembl_gene_id\tGene stable ID\tfeature_page\n".into());
+        assert!(matches!(changed, Err(BiomartError::Csv(_))));
+        assert!(parse::filters(String::new()).unwrap().is_empty());
+    }
+
+    #[test]
+    fn completion_stamp() {
+        let query = QueryBuilder::new().dataset("hsapiens_gene_ensembl").build();
+        assert!(query.completion_stamp());
+        assert!(query.to_string().contains("completionStamp=\"1\""));
+        let query = QueryBuilder::new().completion_stamp(false).build();
+        assert!(!query.completion_stamp());
+
+        let raw = "NCBI gene ID\n837\n[success]\n".to_string();
+        assert_eq!("NCBI gene ID\n837\n", strip_completion_stamp(raw).unwrap());
+        let truncated = "NCBI gene ID\n837\n83".to_string();
+        match strip_completion_stamp(truncated) {
+            Err(BiomartError::Incomplete) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn build_client() {
+        let mart_client = MartClient::builder("http://ensembl.org:80/biomart/martservice")
+            .timeout(None)
+            .connect_timeout(Duration::from_secs(5))
+            .header(USER_AGENT, HeaderValue::from_static("rust-biomart-test"))
+            .build();
+        assert!(mart_client.is_ok());
+        for rate in &[0., -1., f64::NAN, f64::INFINITY] {
+            assert!(
+                MartClient::builder("http://ensembl.org:80/biomart/martservice")
+                    .rate_limit(*rate, 1)
+                    .build()
+                    .is_err()
+            );
+        }
+    }
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Gene {
+        ensembl_gene_id: String,
+        entrezgene_id: Option<u64>,
+    }
+
+    #[test]
+    fn deserialize_rows() {
+        let response = Response {
+            raw: "Gene stable ID\tNCBI gene ID\nENSG00000141510\t7157\nENSG00000284792\t\n".into(),
+            query: QueryBuilder::new()
+                .attributes(vec!["ensembl_gene_id", "entrezgene_id"])
+                .build(),
+        };
+        let genes: Vec<Gene> = response.deserialize_by_attribute().unwrap();
+        assert_eq!(
+            vec![
+                Gene {
+                    ensembl_gene_id: "ENSG00000141510".into(),
+                    entrezgene_id: Some(7157)
+                },
+                Gene {
+                    ensembl_gene_id: "ENSG00000284792".into(),
+                    entrezgene_id: None
+                },
+            ],
+            genes
+        );
+        let rows: Vec<HashMap<String, String>> = response.deserialize().unwrap();
+        assert_eq!("7157", rows[0]["NCBI gene ID"]);
+
+        let response = Response {
+            raw: "Gene stable ID\tNCBI gene ID\nENSG00000141510\tn/a\n".into(),
+            query: QueryBuilder::new()
+                .attributes(vec!["ensembl_gene_id", "entrezgene_id"])
+                .build(),
+        };
+        match response.deserialize_by_attribute::<Gene>() {
+            Err(BiomartError::Record { line, column, .. }) => {
+                assert_eq!(Some(2), line);
+                assert_eq!(Some("entrezgene_id".to_string()), column);
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn columns_by_attribute() {
+        let response = Response {
+            raw: "Gene stable ID\tNCBI gene ID\nENSG00000141510\t7157\nENSG00000284792\t\n".into(),
+            query: QueryBuilder::new()
+                .attributes(vec!["ensembl_gene_id", "entrezgene_id"])
+                .build(),
+        };
+        assert_eq!(
+            vec!["ensembl_gene_id", "entrezgene_id"],
+            response.attributes()
+        );
+        assert_eq!(Some(1), response.column_index("entrezgene_id"));
+        assert_eq!(
+            Some(vec!["7157".to_string(), "".to_string()]),
+            response.column("entrezgene_id")
+        );
+        assert_eq!(None, response.column("hgnc_symbol"));
+    }
+
+    #[test]
+    fn malformed_records() {
+        let response = Response {
+            raw: "Gene stable ID\tGene description\ng1\ttumor \"protein\" p53\ng2\tBRCA2\textra\ng3\tNRAS\n"
+                .into(),
+            query: QueryBuilder::new()
+                .attributes(vec!["ensembl_gene_id", "description"])
+                .build(),
+        };
+        assert_eq!(
+            vec!["Gene stable ID", "Gene description"],
+            response.try_header().unwrap().iter().collect::<Vec<_>>()
+        );
+        match response.try_records() {
+            Err(BiomartError::Record { line, .. }) => assert_eq!(Some(3), line),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        let (records, skipped) = response.records_lossless();
+        assert_eq!(2, records.len());
+        assert_eq!("tumor \"protein\" p53", &records[0][1]);
+        assert_eq!(1, skipped.len());
+        assert_eq!(2, response.records().len());
+    }
+
+    /// Serves `bodies` in order, one per connection, and returns the URL of the server.
+    fn serve(bodies: Vec<&'static str>) -> String {
+        use std::io::{Read, Write};
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!(
+            "http://{}/biomart/martservice",
+            listener.local_addr().unwrap()
+        );
+        std::thread::spawn(move || {
+            for body in bodies {
+                let (mut stream, _) = listener.accept().unwrap();
+                let mut request = vec![];
+                let mut buffer = [0; 1024];
+                while !request.ends_with(b"\r\n\r\n") {
+                    let n = stream.read(&mut buffer).unwrap();
+                    request.extend_from_slice(&buffer[..n]);
+                }
+                write!(
+                    stream,
+                    "HTTP/1.1 200 OK\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                )
+                .unwrap();
+            }
+        });
+        url
+    }
+
+    #[test]
+    fn validate_typed_filters() {
+        let filters =
+            "chromosome_name\tChromosome/scaffold name\t[1,2,X]\t\tfilters\tlist\t=\tx\ty\n\
+                       start\tStart (bp)\t[]\t\tfilters\ttext\t>=\tx\ty\n\
+                       end\tEnd (bp)\t[]\t\tfilters\ttext\t>=\tx\ty\n";
+        let attributes = "ensembl_gene_id\tGene stable ID\t\tfeature_page\ttsv\tx\ty\n";
+        let mart_client = MartClient::new(serve(vec![filters, attributes]));
+        let error = QueryBuilder::new()
+            .mart("ENSEMBL_MART_ENSEMBL")
+            .dataset("hsapiens_gene_ensembl")
+            .attribute("ensembl_gene_id")
+            .range("1", 1000, 2000)
+            .filter_ids("chromosome_name", vec!["1"])
+            .build_validated(&mart_client)
+            .unwrap_err();
+        match error {
+            BiomartError::Validation(issues) => {
+                let filters = issues
+                    .iter()
+                    .map(|issue| match issue {
+                        ValidationIssue::UnsupportedOperation { filter, .. } => filter.as_str(),
+                        _ => panic!("unexpected issue {}", issue),
+                    })
+                    .collect::<Vec<_>>();
+                assert_eq!(vec!["end", "chromosome_name"], filters);
+            }
+            _ => panic!("unexpected error {}", error),
+        }
+    }
+
+    #[test]
+    fn query_options() {
+        let query = QueryBuilder::new()
+            .virtual_schema_name("plants_mart")
+            .unique_rows(false)
+            .header(false)
+            .limit(10)
+            .request_id("my-pipeline")
+            .build();
+        let xml = query.to_string();
+        for expected in &[
+            "virtualSchemaName=\"plants_mart\"",
+            "uniqueRows=\"0\"",
+            "header=\"0\"",
+            "limitSize=\"10\"",
+            "requestid=\"my-pipeline\"",
+        ] {
+            assert!(xml.contains(expected), "{} not in {}", expected, xml);
+        }
+        assert!(!query.header());
+
+        let response = Response {
+            raw: "g1\tTP53\ng2\tBRCA2\n".into(),
+            query: QueryBuilder::new()
+                .attributes(vec!["ensembl_gene_id", "external_gene_name"])
+                .header(false)
+                .build(),
+        };
+        assert!(response.header().is_none());
+        assert_eq!(2, response.records().len());
+        assert_eq!(
+            Some(vec!["g1".to_string(), "g2".to_string()]),
+            response.column("ensembl_gene_id")
+        );
+        let genes: Vec<HashMap<String, String>> = response.deserialize_by_attribute().unwrap();
+        assert_eq!("TP53", genes[0]["external_gene_name"]);
+    }
+
+    #[test]
+    fn linked_datasets() {
+        let query = QueryBuilder::new()
+            .dataset("hsapiens_gene_ensembl")
+            .attribute("ensembl_gene_id")
+            .linked_dataset("mmusculus_gene_ensembl")
+            .attribute("external_gene_name")
+            .filter_bool("with_mgi", true)
+            .dataset("hsapiens_gene_ensembl")
+            .filter("chromosome_name", vec!["17"])
+            .build();
+        let datasets = query
+            .inner
+            .children
+            .iter()
+            .filter_map(XMLNode::as_element)
+            .collect::<Vec<_>>();
+        assert_eq!(2, datasets.len());
+        assert_eq!("hsapiens_gene_ensembl", datasets[0].attributes["name"]);
+        assert_eq!(
+            "chromosome_name",
+            datasets[0].get_child("Filter").unwrap().attributes["name"]
+        );
+        assert_eq!("mmusculus_gene_ensembl", datasets[1].attributes["name"]);
+        assert_eq!(
+            "with_mgi",
+            datasets[1].get_child("Filter").unwrap().attributes["name"]
+        );
+        assert_eq!(
+            vec!["ensembl_gene_id", "external_gene_name"],
+            query.attribute_names()
+        );
+
+        // the same attribute from both datasets
+        let query = QueryBuilder::new()
+            .dataset("hsapiens_gene_ensembl")
+            .attributes(vec!["ensembl_gene_id", "external_gene_name"])
+            .linked_dataset("mmusculus_gene_ensembl")
+            .attribute("ensembl_gene_id")
+            .build();
+        let response = Response {
+            raw: "Gene stable ID\tGene name\tGene stable ID\ng1\tTP53\tm1\n".into(),
+            query,
+        };
+        assert_eq!(
+            vec![
+                "hsapiens_gene_ensembl.ensembl_gene_id",
+                "external_gene_name",
+                "mmusculus_gene_ensembl.ensembl_gene_id"
+            ],
+            response.attributes()
+        );
+        assert_eq!(None, response.column("ensembl_gene_id"));
+        assert_eq!(
+            Some(vec!["m1".to_string()]),
+            response.column("mmusculus_gene_ensembl.ensembl_gene_id")
+        );
+        assert_eq!(
+            Some(1),
+            response.column_index("hsapiens_gene_ensembl.external_gene_name")
+        );
+        let rows: Vec<HashMap<String, String>> = response.deserialize_by_attribute().unwrap();
+        assert_eq!("g1", rows[0]["hsapiens_gene_ensembl.ensembl_gene_id"]);
+    }
+
+    #[test]
+    fn typed_filters() {
+        let query = QueryBuilder::new()
+            .range("17", 7_661_779, 7_687_538)
+            .filter_ids(
+                "ensembl_gene_id",
+                vec!["ENSG00000141510", "ENSG00000139618"],
+            )
+            .filter_text("band_start", "p13.1")
+            .filter_only("with_hgnc")
+            .filter_excluded("with_refseq_mrna")
+            .build();
+        let dataset = query.inner.get_child("Dataset").unwrap();
+        let filters = dataset
+            .children
+            .iter()
+            .filter_map(XMLNode::as_element)
+            .map(|filter| {
+                (
+                    filter.attributes["name"].as_str(),
+                    filter.attributes.get("value").map(String::as_str),
+                    filter.attributes.get("excluded").map(String::as_str),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("chromosome_name", Some("17"), None),
+                ("start", Some("7661779"), None),
+                ("end", Some("7687538"), None),
+                (
+                    "ensembl_gene_id",
+                    Some("ENSG00000141510,ENSG00000139618"),
+                    None
+                ),
+                ("band_start", Some("p13.1"), None),
+                ("with_hgnc", None, Some("0")),
+                ("with_refseq_mrna", None, Some("1")),
+            ],
+            filters
+        );
+    }
+
+    #[test]
+    fn query_from_xml() {
+        let xml = r#"<?xml version="1.0" encoding="UTF-8"?>
+<!DOCTYPE Query>
+<Query virtualSchemaName="default" formatter="CSV" header="0" uniqueRows="1" count="" datasetConfigVersion="0.6" completionStamp="1" limitSize="5">
+    <Dataset name="hsapiens_gene_ensembl" interface="default">
+        <Filter name="chromosome_name" value="1,2,X"/>
+        <Filter name="with_hgnc" excluded="0"/>
+        <Filter name="transcript_tsl" excluded="1"/>
+        <Attribute name="ensembl_gene_id"/>
+        <Attribute name="hgnc_symbol"/>
+    </Dataset>
+    <Dataset name="mmusculus_gene_ensembl" interface="default">
+        <Attribute name="ensembl_gene_id"/>
+    </Dataset>
+</Query>"#;
+        let query = Query::from_xml(xml).unwrap();
+        let builder = QueryBuilder::from_query(&query).unwrap();
+        assert_eq!(Formatter::Csv, builder.formatter);
+        assert_eq!(Some(5), builder.limit);
+        assert!(!builder.header && builder.unique_rows && builder.completion_stamp);
+        assert_eq!(2, builder.datasets.len());
+        assert_eq!(3, builder.datasets[0].filters.len());
+        let rebuilt = builder.build();
+        assert_eq!(query.inner, rebuilt.inner);
+        let reparsed = Query::from_xml(&rebuilt.to_string()).unwrap();
+        assert_eq!(rebuilt.inner, reparsed.inner);
+
+        assert!(Query::from_xml("<Query><Dataset").is_err());
+        assert!(Query::from_xml("<MartRegistry/>").is_err());
+        let without_dataset = Query::from_xml("<Query formatter=\"TSV\"/>").unwrap();
+        assert!(QueryBuilder::from_query(&without_dataset).is_err());
+        let count = Query::from_xml(&xml.replace("count=\"\"", "count=\"1\"")).unwrap();
+        assert!(QueryBuilder::from_query(&count).is_err());
+    }
+
+    #[test]
+    fn count_query() {
+        let query = QueryBuilder::new()
+            .formatter(Formatter::Fasta)
+            .limit(10)
+            .build()
+            .as_count();
+        assert!(query.to_string().contains("count=\"1\""));
+        assert!(!query.to_string().contains("limitSize"));
+        assert!(!query.completion_stamp());
+        assert_eq!(Some(Formatter::Tsv), query.formatter());
+
+        assert_eq!(37, parse::count("37\n".into()).unwrap());
+        match parse::count("Gene stable ID\nENSG00000198888\n".into()) {
+            Err(BiomartError::UnexpectedBody { snippet, .. }) => {
+                assert!(snippet.starts_with("Gene stable ID"))
+            }
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn parse_formats() {
+        let mut query = QueryBuilder::new();
+        query.attributes(vec!["ensembl_gene_id", "description"]);
+        let response = Response {
+            raw: "Gene stable ID,Gene description\ng1,\"tumor protein, p53\"\n".into(),
+            query: query.formatter(Formatter::Csv).build(),
+        };
+        assert!(response.query().to_string().contains("formatter=\"CSV\""));
+        assert_eq!("tumor protein, p53", &response.try_records().unwrap()[0][1]);
+
+        let response = Response {
+            raw: "1\tensembl\tgene\t100\t200\t.\t+\t.\tgene_id \"g1\";\n".into(),
+            query: query.formatter(Formatter::Gtf).build(),
+        };
+        assert!(response.header().is_none());
+        assert_eq!(1, response.try_records().unwrap().len());
+
+        let response = Response {
+            raw: ">g1\nMEEPQSDPSV\n".into(),
+            query: query.formatter(Formatter::Fasta).build(),
+        };
+        assert!(response.try_records().is_err());
+        assert_eq!("MEEPQSDPSV", response.fasta().unwrap()[0].sequence());
+
+        let mart_client = MartClient::new("http://localhost:1/biomart/martservice");
+        match mart_client.query(&query.formatter(Formatter::Xls).build()) {
+            Err(BiomartError::InvalidQuery(_)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
 }
@@ -0,0 +1,172 @@
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Token bucket allowing `rate` requests per second on average with bursts of up to `capacity`.
+pub(crate) struct TokenBucket {
+    rate: f64,
+    capacity: f64,
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(rate: f64, burst: u32) -> Self {
+        let capacity = f64::from(burst.max(1));
+        TokenBucket {
+            rate,
+            capacity,
+            tokens: capacity,
+            last: Instant::now(),
+        }
+    }
+
+    /// Takes a token and returns how long the caller has to wait before using it.
+    /// Tokens may be borrowed from the future, so concurrent callers queue up
+    /// instead of all waking up at the same time.
+    pub(crate) fn reserve(&mut self) -> Duration {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.capacity);
+        self.last = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::from_secs(0)
+        } else {
+            Duration::from_secs_f64(-self.tokens / self.rate)
+        }
+    }
+}
+
+/// Counting semaphore for blocking callers.
+struct Semaphore {
+    permits: Mutex<usize>,
+    released: Condvar,
+}
+
+/// Returns its slot to the semaphore when dropped.
+pub(crate) struct Permit {
+    semaphore: Arc<Semaphore>,
+}
+
+impl Drop for Permit {
+    fn drop(&mut self) {
+        *self.semaphore.permits.lock().unwrap() += 1;
+        self.semaphore.released.notify_one();
+    }
+}
+
+/// Rate limit and cap on concurrent requests of a `MartClient`.
+#[derive(Default)]
+pub(crate) struct Limiter {
+    bucket: Option<Mutex<TokenBucket>>,
+    in_flight: Option<Arc<Semaphore>>,
+}
+
+impl Limiter {
+    pub(crate) fn new(rate_limit: Option<(f64, u32)>, max_in_flight: Option<usize>) -> Self {
+        Limiter {
+            bucket: rate_limit.map(|(rate, burst)| Mutex::new(TokenBucket::new(rate, burst))),
+            in_flight: max_in_flight.map(|max| {
+                Arc::new(Semaphore {
+                    permits: Mutex::new(max.max(1)),
+                    released: Condvar::new(),
+                })
+            }),
+        }
+    }
+
+    /// Blocks until a request may be sent. The request counts as in flight
+    /// until the returned permit is dropped.
+    pub(crate) fn acquire(&self) -> Option<Permit> {
+        let permit = self.in_flight.as_ref().map(|semaphore| {
+            let mut permits = semaphore.permits.lock().unwrap();
+            while *permits == 0 {
+                permits = semaphore.released.wait(permits).unwrap();
+            }
+            *permits -= 1;
+            Permit {
+                semaphore: semaphore.clone(),
+            }
+        });
+        if let Some(bucket) = &self.bucket {
+            let wait = bucket.lock().unwrap().reserve();
+            thread::sleep(wait);
+        }
+        permit
+    }
+}
+
+/// Async counterpart of `Limiter`.
+#[cfg(feature = "async")]
+#[derive(Default)]
+pub(crate) struct AsyncLimiter {
+    bucket: Option<Mutex<TokenBucket>>,
+    in_flight: Option<tokio::sync::Semaphore>,
+}
+
+#[cfg(feature = "async")]
+impl AsyncLimiter {
+    pub(crate) fn new(rate_limit: Option<(f64, u32)>, max_in_flight: Option<usize>) -> Self {
+        AsyncLimiter {
+            bucket: rate_limit.map(|(rate, burst)| Mutex::new(TokenBucket::new(rate, burst))),
+            in_flight: max_in_flight.map(|max| tokio::sync::Semaphore::new(max.max(1))),
+        }
+    }
+
+    pub(crate) async fn acquire(&self) -> Option<tokio::sync::SemaphorePermit<'_>> {
+        let permit = match &self.in_flight {
+            Some(semaphore) => Some(semaphore.acquire().await),
+            None => None,
+        };
+        if let Some(bucket) = &self.bucket {
+            let wait = bucket.lock().unwrap().reserve();
+            tokio::time::delay_for(wait).await;
+        }
+        permit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::Duration;
+
+    use crate::limit::{Limiter, TokenBucket};
+
+    #[test]
+    fn token_bucket() {
+        let mut bucket = TokenBucket::new(2., 2);
+        assert_eq!(Duration::from_secs(0), bucket.reserve());
+        assert_eq!(Duration::from_secs(0), bucket.reserve());
+        let wait = bucket.reserve();
+        assert!(wait > Duration::from_millis(400) && wait <= Duration::from_millis(500));
+        let wait = bucket.reserve();
+        assert!(wait > Duration::from_millis(900) && wait <= Duration::from_millis(1000));
+    }
+
+    #[test]
+    fn max_in_flight() {
+        let limiter = Arc::new(Limiter::new(None, Some(2)));
+        let current = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+        let handles: Vec<_> = (0..8)
+            .map(|_| {
+                let (limiter, current, peak) = (limiter.clone(), current.clone(), peak.clone());
+                thread::spawn(move || {
+                    let _permit = limiter.acquire();
+                    let n = current.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(n, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(10));
+                    current.fetch_sub(1, Ordering::SeqCst);
+                })
+            })
+            .collect();
+        for handle in handles {
+            handle.join().unwrap();
+        }
+        assert!(peak.load(Ordering::SeqCst) <= 2);
+    }
+}
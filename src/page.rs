@@ -0,0 +1,271 @@
+use std::collections::{HashMap, HashSet};
+
+use csv::StringRecord;
+
+use crate::{AttributeInfo, BiomartError, Formatter, Query, QueryBuilder, Response};
+
+/// Groups `attributes` by the attribute page they belong to. BioMart only accepts queries
+/// with attributes of a single page; a single group is returned if one page holds all of them.
+/// Otherwise each attribute is assigned to the first page listing it.
+/// Attributes missing from `metadata` are left out.
+pub(crate) fn group_by_page(
+    attributes: &[String],
+    metadata: &[AttributeInfo],
+) -> Vec<(String, Vec<String>)> {
+    let mut pages: Vec<(String, Vec<String>)> = vec![];
+    for info in metadata {
+        match pages.iter_mut().find(|(page, _)| page == info.page()) {
+            Some((_, names)) => names.push(info.name().clone()),
+            None => pages.push((info.page().clone(), vec![info.name().clone()])),
+        }
+    }
+    let known = attributes
+        .iter()
+        .filter(|name| metadata.iter().any(|info| info.name() == *name))
+        .cloned()
+        .collect::<Vec<_>>();
+    if let Some((page, _)) = pages
+        .iter()
+        .find(|(_, names)| known.iter().all(|name| names.contains(name)))
+    {
+        return vec![(page.clone(), known)];
+    }
+    let mut groups: Vec<(String, Vec<String>)> = vec![];
+    for name in known {
+        let (page, _) = pages
+            .iter()
+            .find(|(_, names)| names.contains(&name))
+            .expect("known attribute");
+        match groups.iter_mut().find(|(p, _)| p == page) {
+            Some((_, names)) => names.push(name),
+            None => groups.push((page.clone(), vec![name])),
+        }
+    }
+    groups
+}
+
+/// Splits `query` into one query per attribute page, each also requesting `key`.
+pub(crate) fn split_by_page(
+    query: &QueryBuilder,
+    metadata: &[AttributeInfo],
+    key: &str,
+) -> Result<Vec<Query>, BiomartError> {
+    if query.datasets.len() > 1 {
+        return Err(BiomartError::InvalidQuery(
+            "Queries with linked datasets cannot be split by attribute page".into(),
+        ));
+    }
+    if query.formatter != Formatter::Tsv {
+        return Err(BiomartError::InvalidQuery(
+            "Only TSV results can be joined across attribute pages".into(),
+        ));
+    }
+    let attributes = &query.datasets[0].attributes;
+    group_by_page(attributes, metadata)
+        .into_iter()
+        .map(|(page, mut names)| {
+            if !metadata
+                .iter()
+                .any(|info| info.name() == key && *info.page() == page)
+            {
+                return Err(BiomartError::InvalidQuery(format!(
+                    "Key attribute {} is not available on attribute page {}",
+                    key, page
+                )));
+            }
+            names.retain(|name| name != key);
+            names.insert(0, key.to_string());
+            let mut sub_query = query.clone();
+            sub_query.datasets[0].attributes = names;
+            Ok(sub_query.build())
+        })
+        .collect()
+}
+
+/// Joins the results of the queries created by `split_by_page` on their first column, `key`.
+/// The result has the key in its first column, followed by the other attributes of `query`
+/// in their original order. This is a full outer join: keys missing from some of the results
+/// are kept, with empty fields for those results. Rows are ordered by the first result
+/// containing their key.
+pub(crate) fn join(
+    query: &QueryBuilder,
+    key: &str,
+    responses: Vec<Response>,
+) -> Result<Response, BiomartError> {
+    let mut columns = vec![key.to_string()];
+    columns.extend(
+        query.datasets[0]
+            .attributes
+            .iter()
+            .filter(|name| *name != key)
+            .cloned(),
+    );
+    // (response, column) holding each output column
+    let sources = columns
+        .iter()
+        .map(|name| {
+            responses
+                .iter()
+                .enumerate()
+                .find_map(|(i, response)| response.column_index(name).map(|j| (i, j)))
+                .ok_or_else(|| {
+                    BiomartError::InvalidQuery(format!("Attribute {} is not available", name))
+                })
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let mut records = vec![];
+    let mut indices = vec![];
+    for response in &responses {
+        let rows = response.try_records()?;
+        let mut index: HashMap<String, Vec<usize>> = HashMap::new();
+        for (i, row) in rows.iter().enumerate() {
+            index
+                .entry(row.get(0).unwrap_or_default().to_string())
+                .or_default()
+                .push(i);
+        }
+        records.push(rows);
+        indices.push(index);
+    }
+
+    let mut raw = String::new();
+    let mut builder = query.clone();
+    builder.datasets[0].attributes = columns;
+    let joined = builder.build();
+    if joined.has_header_line() {
+        let headers = responses
+            .iter()
+            .map(|response| response.try_header())
+            .collect::<Result<Vec<_>, _>>()?;
+        let line = sources
+            .iter()
+            .map(|(i, j)| headers[*i].get(*j).unwrap_or_default())
+            .collect::<Vec<_>>();
+        raw.push_str(&line.join("\t"));
+        raw.push('\n');
+    }
+    let mut keys: Vec<&str> = vec![];
+    let mut seen = HashSet::new();
+    for row in records.iter().flatten() {
+        let id = row.get(0).unwrap_or_default();
+        if seen.insert(id) {
+            keys.push(id);
+        }
+    }
+    let empty = StringRecord::new();
+    for id in keys {
+        // all combinations of matching rows from the results
+        let mut combinations: Vec<Vec<&StringRecord>> = vec![vec![]];
+        for (rows, index) in records.iter().zip(&indices) {
+            let matches = index
+                .get(id)
+                .map(|matches| matches.iter().map(|i| &rows[*i]).collect::<Vec<_>>())
+                .unwrap_or_else(|| vec![&empty]);
+            combinations = combinations
+                .into_iter()
+                .flat_map(|combination| {
+                    matches.iter().map(move |row| {
+                        let mut combination = combination.clone();
+                        combination.push(row);
+                        combination
+                    })
+                })
+                .collect();
+        }
+        for combination in combinations {
+            // the key column, then the other attributes from their results
+            let line = std::iter::once(id)
+                .chain(
+                    sources
+                        .iter()
+                        .skip(1)
+                        .map(|(i, j)| combination[*i].get(*j).unwrap_or_default()),
+                )
+                .collect::<Vec<_>>();
+            raw.push_str(&line.join("\t"));
+            raw.push('\n');
+        }
+    }
+    Ok(Response { raw, query: joined })
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::page::{group_by_page, join, split_by_page};
+    use crate::{parse, QueryBuilder, Response};
+
+    fn metadata() -> Vec<crate::AttributeInfo> {
+        parse::attributes(
+            "ensembl_gene_id\tGene stable ID\t\tfeature_page\ttsv\tx\ty\n\
+             external_gene_name\tGene name\t\tfeature_page\ttsv\tx\ty\n\
+             ensembl_gene_id\tGene stable ID\t\thomologs\ttsv\tx\ty\n\
+             mmusculus_homolog_ensembl_gene\tMouse gene stable ID\t\thomologs\ttsv\tx\ty\n"
+                .into(),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn group_attributes_by_page() {
+        let metadata = metadata();
+        let same_page = vec!["ensembl_gene_id".into(), "external_gene_name".into()];
+        assert_eq!(
+            vec![("feature_page".to_string(), same_page.clone())],
+            group_by_page(&same_page, &metadata)
+        );
+        let mixed = vec![
+            "ensembl_gene_id".into(),
+            "mmusculus_homolog_ensembl_gene".into(),
+            "external_gene_name".into(),
+        ];
+        assert_eq!(
+            vec![
+                (
+                    "feature_page".to_string(),
+                    vec!["ensembl_gene_id".into(), "external_gene_name".into()]
+                ),
+                (
+                    "homologs".to_string(),
+                    vec!["mmusculus_homolog_ensembl_gene".into()]
+                ),
+            ],
+            group_by_page(&mixed, &metadata)
+        );
+    }
+
+    #[test]
+    fn split_and_join() {
+        let mut query = QueryBuilder::new();
+        query
+            .dataset("hsapiens_gene_ensembl")
+            .attributes(vec!["external_gene_name", "mmusculus_homolog_ensembl_gene"])
+            .filter("ensembl_gene_id", vec!["g1", "g2"]);
+        let queries = split_by_page(&query, &metadata(), "ensembl_gene_id").unwrap();
+        assert_eq!(2, queries.len());
+        assert_eq!(
+            vec!["ensembl_gene_id", "mmusculus_homolog_ensembl_gene"],
+            queries[1].attribute_names()
+        );
+        assert!(split_by_page(&query, &metadata(), "hgnc_symbol").is_err());
+
+        let responses = vec![
+            Response {
+                raw: "Gene stable ID\tGene name\ng1\tTP53\ng2\tBRCA2\n".into(),
+                query: queries[0].clone(),
+            },
+            Response {
+                raw: "Gene stable ID\tMouse gene stable ID\ng1\tm1\ng1\tm2\ng3\tm3\n".into(),
+                query: queries[1].clone(),
+            },
+        ];
+        let joined = join(&query, "ensembl_gene_id", responses).unwrap();
+        assert_eq!(
+            "Gene stable ID\tGene name\tMouse gene stable ID\ng1\tTP53\tm1\ng1\tTP53\tm2\ng2\tBRCA2\t\ng3\t\tm3\n",
+            joined.raw()
+        );
+        assert_eq!(
+            Some(2),
+            joined.column_index("mmusculus_homolog_ensembl_gene")
+        );
+    }
+}
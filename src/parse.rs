@@ -0,0 +1,108 @@
+use serde::de::DeserializeOwned;
+use serde_xml_rs::from_reader;
+use xmltree::Element;
+
+use crate::error::{check_body, snippet};
+use crate::{
+    AttributeInfo, BiomartError, DatasetConfiguration, DatasetInfo, FilterInfo, Formatter,
+    MartInfo, MartRegistry, Query, Response, COMPLETION_STAMP,
+};
+
+pub(crate) fn registry(xml: String) -> Result<Vec<MartInfo>, BiomartError> {
+    let registry: MartRegistry =
+        from_reader(xml.as_bytes()).map_err(|source| BiomartError::Xml {
+            source,
+            snippet: snippet(&xml),
+        })?;
+    Ok(registry.marts)
+}
+
+/// Deserializes the rows of a metadata listing, failing on the first malformed row
+/// (e.g. if the server changed the number of columns).
+fn metadata<T: DeserializeOwned>(tsv: &str) -> Result<Vec<T>, BiomartError> {
+    Ok(csv::ReaderBuilder::new()
+        .has_headers(false)
+        .delimiter(b'\t')
+        .from_reader(tsv.trim().as_bytes())
+        .deserialize::<T>()
+        .collect::<Result<Vec<_>, _>>()?)
+}
+
+pub(crate) fn datasets(tsv: String) -> Result<Vec<DatasetInfo>, BiomartError> {
+    metadata(&tsv)
+}
+
+pub(crate) fn filters(tsv: String) -> Result<Vec<FilterInfo>, BiomartError> {
+    Ok(metadata::<FilterInfo>(&tsv)?
+        .into_iter()
+        // FIXME: write deserializer that can handle Vec<String> representations like "[v_1, v_2, …, v_n]"
+        .map(|mut info| match info.options.len() {
+            0 => info,
+            1 => {
+                let s: String = info.options[0]
+                    .trim_matches(|c| c == '[' || c == ']')
+                    .into();
+                if !s.is_empty() {
+                    info.options[0] = s;
+                } else {
+                    info.options.clear();
+                }
+                info
+            }
+            _ => {
+                let n = info.options.len() - 1;
+                info.options[0] = info.options[0].trim_matches('[').into();
+                info.options[n] = info.options[n].trim_matches(']').into();
+                info
+            }
+        })
+        .collect())
+}
+
+pub(crate) fn attributes(tsv: String) -> Result<Vec<AttributeInfo>, BiomartError> {
+    metadata(&tsv)
+}
+
+pub(crate) fn configuration(xml: String) -> Result<DatasetConfiguration, BiomartError> {
+    let element =
+        Element::parse(xml.trim().as_bytes()).map_err(|_| BiomartError::UnexpectedBody {
+            expected: "a dataset configuration",
+            snippet: snippet(&xml),
+        })?;
+    DatasetConfiguration::from_element(&element)
+}
+
+pub(crate) fn query_result(raw: String, query: &Query) -> Result<Response, BiomartError> {
+    let raw = check_body(raw, query.formatter() == Some(Formatter::Html))?;
+    let raw = if query.completion_stamp() {
+        strip_completion_stamp(raw)?
+    } else {
+        raw
+    };
+    Ok(Response {
+        raw,
+        query: query.clone(),
+    })
+}
+
+/// Parses the body of a query with `count='1'`, which holds just the number of rows.
+pub(crate) fn count(raw: String) -> Result<u64, BiomartError> {
+    raw.trim()
+        .parse()
+        .map_err(|_| BiomartError::UnexpectedBody {
+            expected: "the number of rows",
+            snippet: snippet(&raw),
+        })
+}
+
+/// Removes the trailing `[success]` line BioMart appends to complete results
+/// when the query has `completionStamp='1'`.
+pub(crate) fn strip_completion_stamp(mut raw: String) -> Result<String, BiomartError> {
+    let len = raw.trim_end().len();
+    if raw[..len].ends_with(COMPLETION_STAMP) {
+        raw.truncate(len - COMPLETION_STAMP.len());
+        Ok(raw)
+    } else {
+        Err(BiomartError::Incomplete)
+    }
+}
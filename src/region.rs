@@ -0,0 +1,239 @@
+use std::io::BufRead;
+
+use getset::{CopyGetters, Getters};
+
+use crate::{BiomartError, QueryBuilder};
+
+/// Strand of a `Region`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Strand {
+    Forward,
+    Reverse,
+}
+
+impl Strand {
+    /// The strand as written in Ensembl filter values, `1` or `-1`.
+    pub fn value(self) -> &'static str {
+        match self {
+            Strand::Forward => "1",
+            Strand::Reverse => "-1",
+        }
+    }
+}
+
+/// A range on a chromosome, 1-based and inclusive like Ensembl coordinates.
+///
+/// A leading `chr` is removed from the chromosome name, since Ensembl datasets name
+/// chromosomes `17` rather than `chr17`.
+#[derive(Debug, Clone, PartialEq, Eq, Getters, CopyGetters)]
+pub struct Region {
+    #[getset(get = "pub")]
+    chromosome: String,
+    #[getset(get_copy = "pub")]
+    start: u64,
+    #[getset(get_copy = "pub")]
+    end: u64,
+    #[getset(get_copy = "pub")]
+    strand: Option<Strand>,
+}
+
+impl Region {
+    pub fn new<S: Into<String>>(chromosome: S, start: u64, end: u64) -> Self {
+        Region {
+            chromosome: chromosome_name(chromosome.into()),
+            start,
+            end,
+            strand: None,
+        }
+    }
+
+    /// Restricts the region to features on `strand`.
+    pub fn with_strand(mut self, strand: Strand) -> Self {
+        self.strand = Some(strand);
+        self
+    }
+
+    /// Reads regions from a BED file, converting its 0-based start positions.
+    /// The strand is taken from the sixth column, if present; comment, `track` and
+    /// `browser` lines are skipped.
+    pub fn from_bed<R: BufRead>(reader: R) -> Result<Vec<Region>, BiomartError> {
+        let mut regions = vec![];
+        for (i, line) in reader.lines().enumerate() {
+            let line = line.map_err(BiomartError::Io)?;
+            if line.trim().is_empty()
+                || line.starts_with('#')
+                || line.starts_with("track")
+                || line.starts_with("browser")
+            {
+                continue;
+            }
+            let error = |column: &str, message: &str| BiomartError::Record {
+                line: Some(i as u64 + 1),
+                column: Some(column.to_string()),
+                message: message.to_string(),
+            };
+            let fields = line.split('\t').collect::<Vec<_>>();
+            if fields.len() < 3 {
+                return Err(error("end", "BED lines need at least 3 columns"));
+            }
+            let start = fields[1]
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| error("start", &e.to_string()))?;
+            let end = fields[2]
+                .trim()
+                .parse::<u64>()
+                .map_err(|e| error("end", &e.to_string()))?;
+            let mut region = Region::new(fields[0].trim(), start + 1, end);
+            region.strand = match fields.get(5).map(|strand| strand.trim()) {
+                Some("+") => Some(Strand::Forward),
+                Some("-") => Some(Strand::Reverse),
+                Some(".") | None => None,
+                Some(_) => return Err(error("strand", "expected +, - or .")),
+            };
+            regions.push(region);
+        }
+        Ok(regions)
+    }
+
+    /// The region in the syntax of the `chromosomal_region` filter, `chromosome:start:end[:strand]`.
+    fn value(&self) -> String {
+        match self.strand {
+            Some(strand) => format!(
+                "{}:{}:{}:{}",
+                self.chromosome,
+                self.start,
+                self.end,
+                strand.value()
+            ),
+            None => format!("{}:{}:{}", self.chromosome, self.start, self.end),
+        }
+    }
+}
+
+fn chromosome_name(chromosome: String) -> String {
+    match chromosome.strip_prefix("chr") {
+        Some(name) => name.to_string(),
+        None => chromosome,
+    }
+}
+
+impl<S: Into<String>> From<(S, u64, u64)> for Region {
+    fn from((chromosome, start, end): (S, u64, u64)) -> Self {
+        Region::new(chromosome, start, end)
+    }
+}
+
+impl<S: Into<String>> From<(S, u64, u64, Strand)> for Region {
+    fn from((chromosome, start, end, strand): (S, u64, u64, Strand)) -> Self {
+        Region::new(chromosome, start, end).with_strand(strand)
+    }
+}
+
+impl QueryBuilder {
+    /// Restricts the result to features overlapping `region`, via the `chromosome_name`,
+    /// `start`, `end` and `strand` filters of Ensembl datasets.
+    pub fn region<R: Into<Region>>(&mut self, region: R) -> &mut Self {
+        let region = region.into();
+        self.range(region.chromosome.clone(), region.start, region.end);
+        if let Some(strand) = region.strand {
+            self.filter("strand", vec![strand.value()]);
+        }
+        self
+    }
+
+    /// Restricts the result to features overlapping any of `regions`, via the
+    /// `chromosomal_region` filter of Ensembl datasets.
+    pub fn regions<R: Into<Region>, I: IntoIterator<Item = R>>(&mut self, regions: I) -> &mut Self {
+        let values = regions
+            .into_iter()
+            .map(|region| region.into().value())
+            .collect::<Vec<_>>();
+        self.filter("chromosomal_region", values)
+    }
+
+    /// Restricts the result to features on `chromosome` between the cytogenetic bands
+    /// `start_band` and `end_band`, e.g. `"p13.1"`.
+    pub fn band<S, B, E>(&mut self, chromosome: S, start_band: B, end_band: E) -> &mut Self
+    where
+        S: Into<String>,
+        B: Into<String>,
+        E: Into<String>,
+    {
+        self.filter("chromosome_name", vec![chromosome_name(chromosome.into())])
+            .filter_text("band_start", start_band)
+            .filter_text("band_end", end_band)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::region::{Region, Strand};
+    use crate::{Query, QueryBuilder};
+    use xmltree::XMLNode;
+
+    fn filter_values(query: &Query) -> Vec<(String, String)> {
+        query
+            .inner
+            .get_child("Dataset")
+            .unwrap()
+            .children
+            .iter()
+            .filter_map(XMLNode::as_element)
+            .map(|filter| {
+                (
+                    filter.attributes["name"].clone(),
+                    filter.attributes["value"].clone(),
+                )
+            })
+            .collect()
+    }
+
+    #[test]
+    fn read_bed() {
+        let bed = "track name=genes\n# TP53\nchr17\t7661778\t7687538\tTP53\t0\t-\nchrX\t100\t200\n";
+        let regions = Region::from_bed(bed.as_bytes()).unwrap();
+        assert_eq!(
+            vec![
+                Region::new("17", 7_661_779, 7_687_538).with_strand(Strand::Reverse),
+                Region::new("X", 101, 200),
+            ],
+            regions
+        );
+        assert!(Region::from_bed("chr1\tone\t200\n".as_bytes()).is_err());
+    }
+
+    #[test]
+    fn region_filters() {
+        let query = QueryBuilder::new()
+            .region(("chr17", 7_661_779, 7_687_538, Strand::Reverse))
+            .build();
+        assert_eq!(
+            vec![
+                ("chromosome_name".to_string(), "17".to_string()),
+                ("start".into(), "7661779".into()),
+                ("end".into(), "7687538".into()),
+                ("strand".into(), "-1".into()),
+            ],
+            filter_values(&query)
+        );
+
+        let query = QueryBuilder::new()
+            .regions(vec![
+                Region::new("17", 100, 200).with_strand(Strand::Forward),
+                Region::new("chrX", 300, 400),
+            ])
+            .build();
+        assert_eq!(
+            vec![(
+                "chromosomal_region".to_string(),
+                "17:100:200:1,X:300:400".to_string()
+            )],
+            filter_values(&query)
+        );
+
+        let query = QueryBuilder::new().band("chr17", "p13.1", "p13.1").build();
+        assert_eq!(3, filter_values(&query).len());
+        assert_eq!("17", filter_values(&query)[0].1);
+    }
+}
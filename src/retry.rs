@@ -0,0 +1,254 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use rand::Rng;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
+use reqwest::StatusCode;
+
+use crate::BiomartError;
+
+/// Describes a failed attempt that is about to be retried, see `RetryPolicy::on_retry`.
+#[derive(Debug)]
+pub struct RetryAttempt<'a> {
+    /// Number of the failed attempt, starting at 1.
+    pub attempt: u32,
+    /// Time to wait before the next attempt.
+    pub delay: Duration,
+    pub error: &'a BiomartError,
+}
+
+/// Decides whether and when failed requests are sent again.
+///
+/// Delays grow exponentially from `base_delay` up to `max_delay`.
+///
+/// # Example
+///
+/// ```
+/// use rust_biomart::{MartClient, RetryPolicy};
+/// use std::time::Duration;
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mart_client = MartClient::builder("http://ensembl.org:80/biomart/martservice")
+///     .retry_policy(
+///         RetryPolicy::new()
+///             .max_attempts(5)
+///             .base_delay(Duration::from_secs(2))
+///             .on_retry(|attempt| eprintln!("attempt {} failed: {}", attempt.attempt, attempt.error)),
+///     )
+///     .build()?;
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct RetryPolicy {
+    max_attempts: u32,
+    base_delay: Duration,
+    max_delay: Duration,
+    jitter: bool,
+    honor_retry_after: bool,
+    statuses: Vec<StatusCode>,
+    retry_transport: bool,
+    retry_incomplete: bool,
+    on_retry: Option<RetryCallback>,
+}
+
+type RetryCallback = Arc<dyn Fn(&RetryAttempt) + Send + Sync>;
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        RetryPolicy {
+            max_attempts: 4,
+            base_delay: Duration::from_secs(1),
+            max_delay: Duration::from_secs(60),
+            jitter: true,
+            honor_retry_after: true,
+            statuses: vec![
+                StatusCode::TOO_MANY_REQUESTS,
+                StatusCode::INTERNAL_SERVER_ERROR,
+                StatusCode::BAD_GATEWAY,
+                StatusCode::SERVICE_UNAVAILABLE,
+                StatusCode::GATEWAY_TIMEOUT,
+            ],
+            retry_transport: true,
+            retry_incomplete: true,
+            on_retry: None,
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// A policy that sends every request exactly once.
+    pub fn none() -> Self {
+        RetryPolicy {
+            max_attempts: 1,
+            ..Self::default()
+        }
+    }
+
+    /// Total number of attempts per request, including the first one. Defaults to 4.
+    pub fn max_attempts(&mut self, max_attempts: u32) -> &mut Self {
+        self.max_attempts = max_attempts.max(1);
+        self
+    }
+
+    /// Delay before the second attempt, doubled for each further attempt. Defaults to 1 second.
+    pub fn base_delay(&mut self, delay: Duration) -> &mut Self {
+        self.base_delay = delay;
+        self
+    }
+
+    /// Upper bound for delays, including those requested via `Retry-After`. Defaults to 60 seconds.
+    pub fn max_delay(&mut self, delay: Duration) -> &mut Self {
+        self.max_delay = delay;
+        self
+    }
+
+    /// Whether delays are randomized to between half and all of their nominal value,
+    /// so that several clients do not retry in lockstep. Enabled by default.
+    pub fn jitter(&mut self, jitter: bool) -> &mut Self {
+        self.jitter = jitter;
+        self
+    }
+
+    /// Whether a `Retry-After` header (in seconds) replaces the computed delay. Enabled by default.
+    pub fn honor_retry_after(&mut self, honor: bool) -> &mut Self {
+        self.honor_retry_after = honor;
+        self
+    }
+
+    /// HTTP status codes that are retried. Defaults to 429, 500, 502, 503 and 504.
+    pub fn statuses<I: IntoIterator<Item = StatusCode>>(&mut self, statuses: I) -> &mut Self {
+        self.statuses = statuses.into_iter().collect();
+        self
+    }
+
+    /// Whether timeouts and connection failures are retried. Enabled by default.
+    pub fn retry_transport(&mut self, retry: bool) -> &mut Self {
+        self.retry_transport = retry;
+        self
+    }
+
+    /// Whether truncated results and HTML (maintenance) pages are retried. Enabled by default.
+    pub fn retry_incomplete(&mut self, retry: bool) -> &mut Self {
+        self.retry_incomplete = retry;
+        self
+    }
+
+    /// Registers a callback invoked before each retry, e.g. for logging.
+    pub fn on_retry<F>(&mut self, callback: F) -> &mut Self
+    where
+        F: Fn(&RetryAttempt) + Send + Sync + 'static,
+    {
+        self.on_retry = Some(Arc::new(callback));
+        self
+    }
+
+    fn is_retryable(&self, error: &BiomartError) -> bool {
+        match error {
+            BiomartError::Status { status, .. } => self.statuses.contains(status),
+            BiomartError::Transport(_) => self.retry_transport && error.is_retryable(),
+            BiomartError::Incomplete | BiomartError::UnexpectedHtml { .. } => self.retry_incomplete,
+            _ => false,
+        }
+    }
+
+    fn backoff(&self, attempt: u32) -> Duration {
+        let factor = 2u32.saturating_pow(attempt.saturating_sub(1));
+        let delay = self
+            .base_delay
+            .checked_mul(factor)
+            .map_or(self.max_delay, |delay| delay.min(self.max_delay));
+        if self.jitter {
+            delay.mul_f64(rand::thread_rng().gen_range(0.5, 1.0))
+        } else {
+            delay
+        }
+    }
+
+    /// Returns the delay before the next attempt, or `None` if `error` is final.
+    pub(crate) fn next_delay(&self, attempt: u32, error: &BiomartError) -> Option<Duration> {
+        if attempt >= self.max_attempts || !self.is_retryable(error) {
+            return None;
+        }
+        let delay = match error {
+            BiomartError::Status {
+                retry_after: Some(retry_after),
+                ..
+            } if self.honor_retry_after => (*retry_after).min(self.max_delay),
+            _ => self.backoff(attempt),
+        };
+        if let Some(callback) = &self.on_retry {
+            callback(&RetryAttempt {
+                attempt,
+                delay,
+                error,
+            });
+        }
+        Some(delay)
+    }
+}
+
+/// Parses a `Retry-After` header given in seconds; HTTP dates are not supported.
+pub(crate) fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    headers
+        .get(RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .trim()
+        .parse()
+        .ok()
+        .map(Duration::from_secs)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use reqwest::StatusCode;
+
+    use crate::{BiomartError, RetryPolicy};
+
+    #[test]
+    fn exponential_backoff() {
+        let mut policy = RetryPolicy::new();
+        policy
+            .max_attempts(4)
+            .base_delay(Duration::from_secs(1))
+            .max_delay(Duration::from_secs(3))
+            .jitter(false);
+        let error = BiomartError::from(StatusCode::BAD_GATEWAY);
+        assert_eq!(Some(Duration::from_secs(1)), policy.next_delay(1, &error));
+        assert_eq!(Some(Duration::from_secs(2)), policy.next_delay(2, &error));
+        assert_eq!(Some(Duration::from_secs(3)), policy.next_delay(3, &error));
+        assert_eq!(None, policy.next_delay(4, &error));
+
+        let error = BiomartError::from(StatusCode::NOT_FOUND);
+        assert_eq!(None, policy.next_delay(1, &error));
+        assert_eq!(
+            None,
+            RetryPolicy::none().next_delay(1, &BiomartError::Incomplete)
+        );
+    }
+
+    #[test]
+    fn retry_after_and_callback() {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let mut policy = RetryPolicy::new();
+        policy.on_retry(move |attempt| {
+            assert_eq!(Duration::from_secs(7), attempt.delay);
+            counter.fetch_add(1, Ordering::SeqCst);
+        });
+        let error = BiomartError::Status {
+            status: StatusCode::SERVICE_UNAVAILABLE,
+            retry_after: Some(Duration::from_secs(7)),
+        };
+        assert_eq!(Some(Duration::from_secs(7)), policy.next_delay(1, &error));
+        assert_eq!(1, calls.load(Ordering::SeqCst));
+    }
+}
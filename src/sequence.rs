@@ -0,0 +1,206 @@
+use crate::{BiomartError, Formatter, Query, QueryBuilder};
+
+/// Kind of sequence retrieved by a `SequenceQuery`, i.e. an attribute of the "sequences" page.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SequenceType {
+    /// Spliced transcript (`cdna`).
+    Cdna,
+    /// Coding sequence only (`coding`).
+    Coding,
+    Peptide,
+    /// 5' untranslated region (`5utr`).
+    Utr5,
+    /// 3' untranslated region (`3utr`).
+    Utr3,
+    /// Unspliced gene (`gene_exon_intron`).
+    GeneExonIntron,
+    /// Unspliced transcript (`transcript_exon_intron`).
+    TranscriptExonIntron,
+    /// Exon sequences (`gene_exon`).
+    GeneExon,
+    /// Flanking region of the gene only (`gene_flank`).
+    GeneFlank,
+    /// Flanking region of the transcript only (`transcript_flank`).
+    TranscriptFlank,
+    /// Flanking region of the coding region of the gene only (`coding_gene_flank`).
+    CodingGeneFlank,
+    /// Flanking region of the coding region of the transcript only (`coding_transcript_flank`).
+    CodingTranscriptFlank,
+}
+
+impl SequenceType {
+    /// Name of the attribute requesting this kind of sequence.
+    pub fn attribute(self) -> &'static str {
+        match self {
+            SequenceType::Cdna => "cdna",
+            SequenceType::Coding => "coding",
+            SequenceType::Peptide => "peptide",
+            SequenceType::Utr5 => "5utr",
+            SequenceType::Utr3 => "3utr",
+            SequenceType::GeneExonIntron => "gene_exon_intron",
+            SequenceType::TranscriptExonIntron => "transcript_exon_intron",
+            SequenceType::GeneExon => "gene_exon",
+            SequenceType::GeneFlank => "gene_flank",
+            SequenceType::TranscriptFlank => "transcript_flank",
+            SequenceType::CodingGeneFlank => "coding_gene_flank",
+            SequenceType::CodingTranscriptFlank => "coding_transcript_flank",
+        }
+    }
+
+    /// Whether the sequence consists of flanking regions only,
+    /// which requires `upstream_flank` or `downstream_flank`.
+    pub fn is_flank(self) -> bool {
+        matches!(
+            self,
+            SequenceType::GeneFlank
+                | SequenceType::TranscriptFlank
+                | SequenceType::CodingGeneFlank
+                | SequenceType::CodingTranscriptFlank
+        )
+    }
+}
+
+/// Query for sequences of genes or transcripts, returned in FASTA format by `MartClient::sequences`.
+///
+/// The header of each sequence holds the values of the header attributes, separated by `|`.
+///
+/// # Example
+///
+/// ```
+/// use rust_biomart::{MartClient, SequenceQuery, SequenceType};
+/// # fn main() -> Result<(), Box<dyn std::error::Error>> {
+/// let mart_client = MartClient::new("http://ensembl.org:80/biomart/martservice");
+/// let query = SequenceQuery::new("hsapiens_gene_ensembl", SequenceType::GeneExonIntron)
+///     .genes(vec!["ENSG00000141510"])
+///     .upstream_flank(100)
+///     .build()?;
+/// for record in mart_client.sequences(&query)? {
+///     let record = record?;
+///     println!("{:?}: {} bp", record.header_fields(), record.sequence().len());
+/// }
+/// # Ok(())
+/// # }
+/// ```
+#[derive(Clone)]
+pub struct SequenceQuery {
+    dataset: String,
+    sequence_type: SequenceType,
+    ids: Option<(String, Vec<String>)>,
+    header_attributes: Vec<String>,
+    upstream_flank: Option<u32>,
+    downstream_flank: Option<u32>,
+}
+
+impl SequenceQuery {
+    /// Sequences of type `sequence_type` from `dataset`, with the gene and transcript ID in the header.
+    pub fn new<S: Into<String>>(dataset: S, sequence_type: SequenceType) -> Self {
+        SequenceQuery {
+            dataset: dataset.into(),
+            sequence_type,
+            ids: None,
+            header_attributes: vec!["ensembl_gene_id".into(), "ensembl_transcript_id".into()],
+            upstream_flank: None,
+            downstream_flank: None,
+        }
+    }
+
+    /// Restricts the result to the genes with the given Ensembl IDs.
+    pub fn genes<T: Into<String>, I: IntoIterator<Item = T>>(&mut self, ids: I) -> &mut Self {
+        self.ids("ensembl_gene_id", ids)
+    }
+
+    /// Restricts the result to the transcripts with the given Ensembl IDs.
+    pub fn transcripts<T: Into<String>, I: IntoIterator<Item = T>>(&mut self, ids: I) -> &mut Self {
+        self.ids("ensembl_transcript_id", ids)
+    }
+
+    /// Restricts the result to the values of the ID filter `filter`, e.g. `"hgnc_symbol"`.
+    pub fn ids<S, T, I>(&mut self, filter: S, ids: I) -> &mut Self
+    where
+        S: Into<String>,
+        T: Into<String>,
+        I: IntoIterator<Item = T>,
+    {
+        self.ids = Some((filter.into(), ids.into_iter().map(Into::into).collect()));
+        self
+    }
+
+    /// Attributes whose values make up the header of each sequence.
+    /// Defaults to `ensembl_gene_id` and `ensembl_transcript_id`.
+    pub fn header_attributes<S: Into<String>, I: IntoIterator<Item = S>>(
+        &mut self,
+        attributes: I,
+    ) -> &mut Self {
+        self.header_attributes = attributes.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Number of bases upstream of the sequence to include.
+    pub fn upstream_flank(&mut self, length: u32) -> &mut Self {
+        self.upstream_flank = Some(length);
+        self
+    }
+
+    /// Number of bases downstream of the sequence to include.
+    pub fn downstream_flank(&mut self, length: u32) -> &mut Self {
+        self.downstream_flank = Some(length);
+        self
+    }
+
+    /// Builds the query, checking that flank-only sequence types have exactly one flank length.
+    pub fn build(&self) -> Result<Query, BiomartError> {
+        if self.sequence_type.is_flank() {
+            match (self.upstream_flank, self.downstream_flank) {
+                (Some(_), None) | (None, Some(_)) => {}
+                _ => {
+                    return Err(BiomartError::InvalidQuery(format!(
+                        "{} sequences require either an upstream or a downstream flank length",
+                        self.sequence_type.attribute()
+                    )))
+                }
+            }
+        }
+        let mut query = QueryBuilder::new();
+        query
+            .dataset(&self.dataset)
+            .formatter(Formatter::Fasta)
+            .attribute(self.sequence_type.attribute())
+            .attributes(&self.header_attributes);
+        if let Some((filter, ids)) = &self.ids {
+            query.filter(filter, ids);
+        }
+        if let Some(length) = self.upstream_flank {
+            query.filter("upstream_flank", vec![length.to_string()]);
+        }
+        if let Some(length) = self.downstream_flank {
+            query.filter("downstream_flank", vec![length.to_string()]);
+        }
+        Ok(query.build())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{Formatter, SequenceQuery, SequenceType};
+
+    #[test]
+    fn build_sequence_query() {
+        let query = SequenceQuery::new("hsapiens_gene_ensembl", SequenceType::Peptide)
+            .transcripts(vec!["ENST00000269305"])
+            .build()
+            .unwrap();
+        assert_eq!(Some(Formatter::Fasta), query.formatter());
+        assert_eq!(
+            vec!["peptide", "ensembl_gene_id", "ensembl_transcript_id"],
+            query.attribute_names()
+        );
+        assert!(query.to_string().contains("value=\"ENST00000269305\""));
+
+        let mut flank = SequenceQuery::new("hsapiens_gene_ensembl", SequenceType::GeneFlank);
+        flank.genes(vec!["ENSG00000141510"]);
+        assert!(flank.build().is_err());
+        let query = flank.upstream_flank(500).build().unwrap();
+        assert!(query.to_string().contains("value=\"500\""));
+        assert!(flank.downstream_flank(500).build().is_err());
+    }
+}
@@ -0,0 +1,352 @@
+use std::io::{self, BufRead, BufReader, Read};
+
+use csv::StringRecord;
+
+use crate::error::check_body;
+use crate::limit::Permit;
+use crate::{reader_builder, BiomartError, FastaRecord, Formatter, COMPLETION_STAMP};
+
+/// Number of bytes read ahead to check for error messages before any data is handed out.
+const PEEK_LENGTH: usize = 1024;
+/// Number of trailing bytes held back until the end of the body, which must contain the completion stamp.
+const STAMP_HOLD_BACK: usize = 64;
+
+/// Raw body of a query result, read directly from the HTTP response.
+///
+/// If the query requests a completion stamp, it is verified and removed;
+/// a missing stamp yields an `io::Error` wrapping `BiomartError::Incomplete`
+/// after the last byte of the body.
+pub struct QueryReader {
+    inner: Box<dyn Read + Send>,
+    pending: Vec<u8>,
+    completion_stamp: bool,
+    eof: bool,
+    incomplete: bool,
+    _permit: Option<Permit>,
+}
+
+impl QueryReader {
+    /// Reads the beginning of `inner` to detect error messages sent in place of the result.
+    /// HTML pages are only accepted as result if `html` is set.
+    pub(crate) fn new<R: Read + Send + 'static>(
+        inner: R,
+        completion_stamp: bool,
+        html: bool,
+        permit: Option<Permit>,
+    ) -> Result<Self, BiomartError> {
+        let mut reader = QueryReader {
+            inner: Box::new(inner),
+            pending: Vec::with_capacity(PEEK_LENGTH),
+            completion_stamp,
+            eof: false,
+            incomplete: false,
+            _permit: permit,
+        };
+        while !reader.eof && reader.pending.len() < PEEK_LENGTH {
+            reader.fill().map_err(from_io)?;
+        }
+        check_body(String::from_utf8_lossy(&reader.pending).into_owned(), html)?;
+        Ok(reader)
+    }
+
+    fn fill(&mut self) -> io::Result<()> {
+        let mut buf = [0u8; 8192];
+        let n = self.inner.read(&mut buf)?;
+        if n == 0 {
+            self.eof = true;
+            if self.completion_stamp {
+                self.incomplete = !self.strip_completion_stamp();
+            }
+        } else {
+            self.pending.extend_from_slice(&buf[..n]);
+        }
+        Ok(())
+    }
+
+    fn strip_completion_stamp(&mut self) -> bool {
+        let len = self
+            .pending
+            .iter()
+            .rposition(|b| !b.is_ascii_whitespace())
+            .map_or(0, |i| i + 1);
+        if self.pending[..len].ends_with(COMPLETION_STAMP.as_bytes()) {
+            self.pending.truncate(len - COMPLETION_STAMP.len());
+            true
+        } else {
+            false
+        }
+    }
+}
+
+impl Read for QueryReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        loop {
+            let available = if self.eof {
+                self.pending.len()
+            } else if self.completion_stamp {
+                self.pending.len().saturating_sub(STAMP_HOLD_BACK)
+            } else {
+                self.pending.len()
+            };
+            if available > 0 {
+                let n = available.min(buf.len());
+                buf[..n].copy_from_slice(&self.pending[..n]);
+                self.pending.drain(..n);
+                return Ok(n);
+            }
+            if self.eof {
+                if self.incomplete {
+                    self.incomplete = false;
+                    return Err(io::Error::new(
+                        io::ErrorKind::UnexpectedEof,
+                        BiomartError::Incomplete,
+                    ));
+                }
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+    }
+}
+
+/// Iterator over the rows of a query result, parsed while the body is being received.
+pub struct QueryRecords {
+    reader: csv::Reader<QueryReader>,
+    header: Option<StringRecord>,
+}
+
+impl QueryRecords {
+    pub(crate) fn new(
+        reader: QueryReader,
+        formatter: Formatter,
+        header: bool,
+    ) -> Result<Self, BiomartError> {
+        let mut reader = reader_builder(formatter)
+            .has_headers(header)
+            .from_reader(reader);
+        let header = if header {
+            Some(reader.headers().map_err(from_csv)?.clone())
+        } else {
+            None
+        };
+        Ok(QueryRecords { reader, header })
+    }
+
+    /// The header line, if the query requested one.
+    pub fn header(&self) -> Option<&StringRecord> {
+        self.header.as_ref()
+    }
+}
+
+impl Iterator for QueryRecords {
+    type Item = Result<StringRecord, BiomartError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let mut record = StringRecord::new();
+        match self.reader.read_record(&mut record) {
+            Ok(true) => Some(Ok(record)),
+            Ok(false) => None,
+            Err(e) => Some(Err(from_csv(e))),
+        }
+    }
+}
+
+/// Iterator over the sequences of a FASTA result, parsed while the body is being received.
+pub struct FastaRecords {
+    reader: BufReader<QueryReader>,
+    header: Option<String>,
+    line: u64,
+}
+
+impl FastaRecords {
+    pub(crate) fn new(reader: QueryReader) -> Self {
+        FastaRecords {
+            reader: BufReader::new(reader),
+            header: None,
+            line: 0,
+        }
+    }
+
+    /// Reads the next line without trailing whitespace, or `None` at the end of the body.
+    fn read_line(&mut self) -> Result<Option<String>, BiomartError> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line).map_err(from_io)? == 0 {
+            return Ok(None);
+        }
+        self.line += 1;
+        line.truncate(line.trim_end().len());
+        Ok(Some(line))
+    }
+
+    fn read_record(&mut self) -> Result<Option<FastaRecord>, BiomartError> {
+        let header = match self.header.take() {
+            Some(header) => header,
+            None => loop {
+                match self.read_line()? {
+                    None => return Ok(None),
+                    Some(line) if line.is_empty() => continue,
+                    Some(line) => match line.strip_prefix('>') {
+                        Some(header) => break header.to_string(),
+                        None => {
+                            return Err(BiomartError::Record {
+                                line: Some(self.line),
+                                column: None,
+                                message: "sequence without header line".into(),
+                            })
+                        }
+                    },
+                }
+            },
+        };
+        let mut sequence = String::new();
+        while let Some(line) = self.read_line()? {
+            if let Some(next) = line.strip_prefix('>') {
+                self.header = Some(next.to_string());
+                break;
+            }
+            sequence.push_str(&line);
+        }
+        Ok(Some(FastaRecord::new(header, sequence)))
+    }
+}
+
+impl Iterator for FastaRecords {
+    type Item = Result<FastaRecord, BiomartError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.read_record().transpose()
+    }
+}
+
+/// Recovers a `BiomartError` passed through an `io::Error`.
+pub(crate) fn from_io(e: io::Error) -> BiomartError {
+    if !matches!(e.get_ref(), Some(inner) if inner.is::<BiomartError>()) {
+        return BiomartError::Io(e);
+    }
+    let kind = e.kind();
+    match e.into_inner().map(|inner| inner.downcast::<BiomartError>()) {
+        Some(Ok(inner)) => *inner,
+        Some(Err(inner)) => BiomartError::Io(io::Error::new(kind, inner)),
+        None => BiomartError::Io(kind.into()),
+    }
+}
+
+fn from_csv(e: csv::Error) -> BiomartError {
+    if !e.is_io_error() {
+        return BiomartError::Csv(e);
+    }
+    match e.into_kind() {
+        csv::ErrorKind::Io(e) => from_io(e),
+        kind => BiomartError::Record {
+            line: None,
+            column: None,
+            message: format!("{:?}", kind),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::{Cursor, Read};
+
+    use crate::stream::{from_io, FastaRecords, QueryReader, QueryRecords};
+    use crate::{BiomartError, Formatter};
+
+    fn reader(body: String, completion_stamp: bool) -> Result<QueryReader, BiomartError> {
+        QueryReader::new(
+            Cursor::new(body.into_bytes()),
+            completion_stamp,
+            false,
+            None,
+        )
+    }
+
+    #[test]
+    fn strip_stamp_while_streaming() {
+        let body = format!(
+            "Gene stable ID\n{}[success]\n",
+            "ENSG00000141510\n".repeat(1000)
+        );
+        let mut raw = String::new();
+        reader(body, true)
+            .unwrap()
+            .read_to_string(&mut raw)
+            .unwrap();
+        assert_eq!(
+            format!("Gene stable ID\n{}", "ENSG00000141510\n".repeat(1000)),
+            raw
+        );
+
+        let truncated = format!("Gene stable ID\n{}ENSG", "ENSG00000141510\n".repeat(1000));
+        let mut raw = Vec::new();
+        let e = reader(truncated, true)
+            .unwrap()
+            .read_to_end(&mut raw)
+            .unwrap_err();
+        match from_io(e) {
+            BiomartError::Incomplete => {}
+            other => panic!("unexpected error: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stream_records() {
+        let body = "Gene stable ID\tGene name\ng1\tTP53\ng2\tBRCA2\n[success]\n".to_string();
+        let records = QueryRecords::new(reader(body, true).unwrap(), Formatter::Tsv, true).unwrap();
+        assert_eq!(
+            vec!["Gene stable ID", "Gene name"],
+            records.header().unwrap().iter().collect::<Vec<_>>()
+        );
+        let rows = records.collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(2, rows.len());
+        assert_eq!("BRCA2", &rows[1][1]);
+
+        let body = "Gene stable ID\tGene name\ng1\tTP53\ng2\tBR".to_string();
+        let mut records =
+            QueryRecords::new(reader(body, true).unwrap(), Formatter::Tsv, true).unwrap();
+        assert_eq!("TP53", &records.next().unwrap().unwrap()[1]);
+        match records.find_map(Result::err) {
+            Some(BiomartError::Incomplete) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn stream_fasta() {
+        let body = format!(
+            ">ENSG00000141510|ENST00000269305\n{}\n{}\n>ENSG00000141510|ENST00000359597\nMEEPQSDPSV\n[success]\n",
+            "A".repeat(60),
+            "C".repeat(20)
+        );
+        let records = FastaRecords::new(reader(body, true).unwrap())
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap();
+        assert_eq!(2, records.len());
+        assert_eq!(
+            vec!["ENSG00000141510", "ENST00000269305"],
+            records[0].header_fields()
+        );
+        assert_eq!(
+            format!("{}{}", "A".repeat(60), "C".repeat(20)),
+            *records[0].sequence()
+        );
+        assert_eq!("MEEPQSDPSV", records[1].sequence());
+
+        let body = ">ENSG00000141510|ENST00000269305\nMEEP".to_string();
+        let mut records = FastaRecords::new(reader(body, true).unwrap());
+        match records.next() {
+            Some(Err(BiomartError::Incomplete)) => {}
+            other => panic!("unexpected result: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn detect_errors_before_streaming() {
+        let body =
+            "Query ERROR: caught BioMart::Exception::Usage: Attribute foo NOT FOUND".to_string();
+        match reader(body, true) {
+            Err(BiomartError::Server(_)) => {}
+            other => panic!("unexpected result: {:?}", other.map(|_| ())),
+        }
+    }
+}
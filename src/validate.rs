@@ -0,0 +1,320 @@
+use std::fmt::{Display, Formatter};
+
+use crate::page::group_by_page;
+use crate::{AttributeInfo, DatasetQuery, FilterInfo, FilterOperation, FilterType};
+
+/// A problem found by `MartClient::validate`, reported via `BiomartError::Validation`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ValidationIssue {
+    UnknownAttribute {
+        dataset: String,
+        name: String,
+        /// The most similar attribute name of the dataset, if any is close enough.
+        suggestion: Option<String>,
+    },
+    UnknownFilter {
+        dataset: String,
+        name: String,
+        /// The most similar filter name of the dataset, if any is close enough.
+        suggestion: Option<String>,
+    },
+    /// A value of a list filter that is not among its options.
+    InvalidOption {
+        dataset: String,
+        filter: String,
+        value: String,
+    },
+    /// A boolean filter was given values instead of being included or excluded.
+    BooleanWithValues { dataset: String, filter: String },
+    /// A filter taking values was included or excluded like a boolean filter.
+    MissingValues { dataset: String, filter: String },
+    /// The filter does not support the operation, according to its type and declared operations.
+    UnsupportedOperation {
+        dataset: String,
+        filter: String,
+        /// The operation used, e.g. `">="` or `"id list"`.
+        operation: String,
+        /// The type and operations declared in `FilterInfo`.
+        declared: String,
+    },
+    /// The attributes come from different attribute pages, which BioMart does not allow
+    /// (see `MartClient::query_by_page` for a workaround).
+    MixedAttributePages {
+        dataset: String,
+        /// Each page with the requested attributes found on it.
+        pages: Vec<(String, Vec<String>)>,
+    },
+}
+
+impl Display for ValidationIssue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> Result<(), std::fmt::Error> {
+        match self {
+            ValidationIssue::UnknownAttribute {
+                dataset,
+                name,
+                suggestion,
+            } => {
+                f.write_fmt(format_args!("Unknown attribute {} in {}", name, dataset))?;
+                write_suggestion(f, suggestion)
+            }
+            ValidationIssue::UnknownFilter {
+                dataset,
+                name,
+                suggestion,
+            } => {
+                f.write_fmt(format_args!("Unknown filter {} in {}", name, dataset))?;
+                write_suggestion(f, suggestion)
+            }
+            ValidationIssue::InvalidOption {
+                dataset,
+                filter,
+                value,
+            } => f.write_fmt(format_args!(
+                "{:?} is not an option of filter {} in {}",
+                value, filter, dataset
+            )),
+            ValidationIssue::BooleanWithValues { dataset, filter } => f.write_fmt(format_args!(
+                "Boolean filter {} in {} takes no values, use QueryBuilder::filter_bool",
+                filter, dataset
+            )),
+            ValidationIssue::MissingValues { dataset, filter } => f.write_fmt(format_args!(
+                "Filter {} in {} requires values",
+                filter, dataset
+            )),
+            ValidationIssue::UnsupportedOperation {
+                dataset,
+                filter,
+                operation,
+                declared,
+            } => f.write_fmt(format_args!(
+                "Filter {} in {} does not support {} (declared: {})",
+                filter, dataset, operation, declared
+            )),
+            ValidationIssue::MixedAttributePages { dataset, pages } => {
+                f.write_fmt(format_args!(
+                    "Attributes of {} come from different pages: ",
+                    dataset
+                ))?;
+                let pages = pages
+                    .iter()
+                    .map(|(page, attributes)| format!("{} ({})", page, attributes.join(", ")))
+                    .collect::<Vec<_>>();
+                f.write_str(&pages.join(", "))
+            }
+        }
+    }
+}
+
+fn write_suggestion(f: &mut Formatter<'_>, suggestion: &Option<String>) -> std::fmt::Result {
+    match suggestion {
+        Some(suggestion) => f.write_fmt(format_args!(", did you mean {}?", suggestion)),
+        None => Ok(()),
+    }
+}
+
+/// Checks the filters and attributes of `dataset` against the metadata of the dataset.
+pub(crate) fn check_dataset(
+    dataset: &DatasetQuery,
+    filters: &[FilterInfo],
+    attributes: &[AttributeInfo],
+) -> Vec<ValidationIssue> {
+    let mut issues = vec![];
+    for name in &dataset.attributes {
+        if !attributes.iter().any(|info| info.name() == name) {
+            issues.push(ValidationIssue::UnknownAttribute {
+                dataset: dataset.name.clone(),
+                name: name.clone(),
+                suggestion: suggest(name, attributes.iter().map(|info| info.name().as_str())),
+            });
+        }
+    }
+    let pages = group_by_page(&dataset.attributes, attributes);
+    if pages.len() > 1 {
+        issues.push(ValidationIssue::MixedAttributePages {
+            dataset: dataset.name.clone(),
+            pages,
+        });
+    }
+    for (name, operation) in &dataset.filters {
+        let info = match filters.iter().find(|info| info.name() == name) {
+            Some(info) => info,
+            None => {
+                issues.push(ValidationIssue::UnknownFilter {
+                    dataset: dataset.name.clone(),
+                    name: name.clone(),
+                    suggestion: suggest(name, filters.iter().map(|info| info.name().as_str())),
+                });
+                continue;
+            }
+        };
+        let unsupported = |operation: &str| ValidationIssue::UnsupportedOperation {
+            dataset: dataset.name.clone(),
+            filter: name.clone(),
+            operation: operation.into(),
+            declared: format!(
+                "{:?} filter with operation {:?}",
+                info.kind(),
+                info.operation()
+            ),
+        };
+        // operations like `>=` or `only` have to be among those declared, if any are
+        let undeclared = || {
+            let declared = info
+                .operation()
+                .split(',')
+                .map(str::trim)
+                .collect::<Vec<_>>();
+            operation
+                .operator()
+                .filter(|operator| !info.operation().is_empty() && !declared.contains(operator))
+                .map(unsupported)
+        };
+        match (info.kind(), operation) {
+            (FilterType::Boolean, FilterOperation::Include | FilterOperation::Exclude) => {
+                issues.extend(undeclared())
+            }
+            (FilterType::Boolean, _) => issues.push(ValidationIssue::BooleanWithValues {
+                dataset: dataset.name.clone(),
+                filter: name.clone(),
+            }),
+            (FilterType::BooleanList, _) | (FilterType::Unknown, _) => {}
+            (FilterType::IdList, FilterOperation::IdList(_)) => {}
+            (_, FilterOperation::IdList(_)) => issues.push(unsupported("id list")),
+            (_, FilterOperation::Match(values)) => {
+                if matches!(info.kind(), FilterType::List) && !info.options().is_empty() {
+                    for value in values {
+                        if !info.options().contains(value) {
+                            issues.push(ValidationIssue::InvalidOption {
+                                dataset: dataset.name.clone(),
+                                filter: name.clone(),
+                                value: value.clone(),
+                            });
+                        }
+                    }
+                }
+            }
+            (_, FilterOperation::Text(_))
+            | (_, FilterOperation::AtLeast(_))
+            | (_, FilterOperation::AtMost(_)) => issues.extend(undeclared()),
+            _ => issues.push(ValidationIssue::MissingValues {
+                dataset: dataset.name.clone(),
+                filter: name.clone(),
+            }),
+        }
+    }
+    issues
+}
+
+/// The candidate closest to `name` by edit distance, if it is close enough to be a likely typo.
+fn suggest<'a, I: Iterator<Item = &'a str>>(name: &str, candidates: I) -> Option<String> {
+    let max_distance = (name.chars().count() / 3).max(2);
+    candidates
+        .map(|candidate| (levenshtein(name, candidate), candidate))
+        .filter(|(distance, _)| *distance <= max_distance)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, candidate)| candidate.to_string())
+}
+
+fn levenshtein(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::validate::{check_dataset, levenshtein, ValidationIssue};
+    use crate::{parse, QueryBuilder};
+
+    #[test]
+    fn edit_distance() {
+        assert_eq!(0, levenshtein("hgnc_symbol", "hgnc_symbol"));
+        assert_eq!(1, levenshtein("hgnc_symbl", "hgnc_symbol"));
+        assert_eq!(3, levenshtein("kitten", "sitting"));
+    }
+
+    #[test]
+    fn check_against_metadata() {
+        let filters = parse::filters(
+            "chromosome_name\tChromosome/scaffold name\t[1,2,X,Y,MT]\t\tfilters\tlist\t=\tx\ty\n\
+             with_hgnc\tWith HGNC Symbol ID(s)\t[only,excluded]\t\tfilters\tboolean\tonly,excluded\tx\ty\n\
+             ensembl_gene_id\tGene stable ID(s)\t[]\t\tfilters\tid_list\t=,in\tx\ty\n\
+             start\tStart (bp)\t[]\t\tfilters\ttext\t>=\tx\ty\n\
+             end\tEnd (bp)\t[]\t\tfilters\ttext\t>=\tx\ty\n"
+                .into(),
+        )
+        .unwrap();
+        let attributes = parse::attributes(
+            "ensembl_gene_id\tGene stable ID\t\tfeature_page\thtml,txt,csv,tsv,xls\tx\ty\n\
+             hgnc_symbol\tHGNC symbol\t\tfeature_page\thtml,txt,csv,tsv,xls\tx\ty\n"
+                .into(),
+        )
+        .unwrap();
+        let mut query = QueryBuilder::new();
+        query
+            .dataset("hsapiens_gene_ensembl")
+            .attributes(vec!["ensembl_gene_id", "hgnc_symbl", "transcript_length"])
+            .filter("chromosome_name", vec!["1", "chr1"])
+            .filter("with_hgnc", vec!["1"])
+            .filter_bool("ensembl_gene_id", true)
+            .filter("band_start", vec!["p36.33"])
+            .range("1", 1000, 2000)
+            .filter_ids("chromosome_name", vec!["1"])
+            .filter_excluded("with_hgnc");
+        let issues = check_dataset(&query.datasets[0], &filters, &attributes);
+        let dataset = "hsapiens_gene_ensembl".to_string();
+        assert_eq!(
+            vec![
+                ValidationIssue::UnknownAttribute {
+                    dataset: dataset.clone(),
+                    name: "hgnc_symbl".into(),
+                    suggestion: Some("hgnc_symbol".into())
+                },
+                ValidationIssue::UnknownAttribute {
+                    dataset: dataset.clone(),
+                    name: "transcript_length".into(),
+                    suggestion: None
+                },
+                ValidationIssue::InvalidOption {
+                    dataset: dataset.clone(),
+                    filter: "chromosome_name".into(),
+                    value: "chr1".into()
+                },
+                ValidationIssue::BooleanWithValues {
+                    dataset: dataset.clone(),
+                    filter: "with_hgnc".into()
+                },
+                ValidationIssue::MissingValues {
+                    dataset: dataset.clone(),
+                    filter: "ensembl_gene_id".into()
+                },
+                ValidationIssue::UnknownFilter {
+                    dataset: dataset.clone(),
+                    name: "band_start".into(),
+                    suggestion: None
+                },
+                ValidationIssue::UnsupportedOperation {
+                    dataset: dataset.clone(),
+                    filter: "end".into(),
+                    operation: "<=".into(),
+                    declared: "Text filter with operation \">=\"".into()
+                },
+                ValidationIssue::UnsupportedOperation {
+                    dataset,
+                    filter: "chromosome_name".into(),
+                    operation: "id list".into(),
+                    declared: "List filter with operation \"=\"".into()
+                },
+            ],
+            issues
+        );
+    }
+}